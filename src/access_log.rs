@@ -0,0 +1,52 @@
+use http::uri::Uri;
+use http::{Method, StatusCode};
+
+use std::time::Duration;
+
+/// A record of a request sent to the upstream, passed to an [`AccessLogger`].
+#[derive(Debug, Clone)]
+pub struct AccessLog {
+    /// The method requested by the client, *e.g.* `HEAD` even if sent as `GET` by
+    /// [`head_as_get()`](crate::ReusedServiceBuilder::head_as_get).
+    pub method: Method,
+    /// The path requested by the client, before rewritten.
+    pub path: String,
+    /// The URI sent to the upstream.
+    pub upstream: Uri,
+    /// `None` if the request failed, *i.e.* the service returned an [`Error`](crate::Error).
+    pub status: Option<StatusCode>,
+    /// The size of the response body, if known when the response is ready, *e.g.* by
+    /// `Content-Length` or [`buffer_response()`](crate::ReusedServiceBuilder::buffer_response).
+    pub bytes: Option<u64>,
+    /// The time from the call of the service until the response is ready.
+    pub duration: Duration,
+}
+
+/// Receives an [`AccessLog`] per request, see
+/// [`Builder::access_log()`](crate::ReusedServiceBuilder::access_log).
+///
+/// This is implemented for closures `Fn(&AccessLog)`.
+///
+/// ```
+/// # use reverse_proxy_service::AccessLog;
+/// # use std::sync::Arc;
+/// let svc_builder = reverse_proxy_service::builder_http("example.com")
+///     .unwrap()
+///     .access_log(Arc::new(|log: &AccessLog| {
+///         println!("{} {} -> {:?} in {:?}", log.method, log.path, log.status, log.duration);
+///     }));
+/// # let _svc: reverse_proxy_service::ReusedService<_, _, hyper::Body> =
+/// #     svc_builder.build(reverse_proxy_service::Identity);
+/// ```
+pub trait AccessLogger: Send + Sync {
+    fn log(&self, log: &AccessLog);
+}
+
+impl<F> AccessLogger for F
+where
+    F: Fn(&AccessLog) + Send + Sync,
+{
+    fn log(&self, log: &AccessLog) {
+        self(log)
+    }
+}
@@ -0,0 +1,110 @@
+use http::header::HeaderValue;
+
+use std::fmt;
+use std::sync::Arc;
+
+pub(crate) type TokenFn = Arc<dyn Fn() -> String + Send + Sync>;
+
+/// The `Authorization` header sent to the upstream.
+#[derive(Clone)]
+pub(crate) enum Authorization {
+    Static(HeaderValue),
+    /// A bearer token fetched per request.
+    Bearer(TokenFn),
+}
+
+impl fmt::Debug for Authorization {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Static(value) => f.debug_tuple("Static").field(value).finish(),
+            Self::Bearer(_) => f.debug_tuple("Bearer").finish_non_exhaustive(),
+        }
+    }
+}
+
+impl Authorization {
+    pub(crate) fn value(&self) -> Option<HeaderValue> {
+        match self {
+            Self::Static(value) => Some(value.clone()),
+            Self::Bearer(token) => match bearer(&token()) {
+                Some(value) => Some(value),
+                None => {
+                    log::error!("The bearer token is not a valid header value");
+                    None
+                }
+            },
+        }
+    }
+}
+
+/// `Basic` credentials for the `Authorization` header, marked as sensitive.
+pub(crate) fn basic(username: &str, password: &str) -> HeaderValue {
+    let credentials = format!("{username}:{password}");
+    let mut value = HeaderValue::from_str(&format!("Basic {}", base64(credentials.as_bytes())))
+        .expect("base64 is a valid header value");
+    value.set_sensitive(true);
+    value
+}
+
+/// `Bearer` token for the `Authorization` header, marked as sensitive.
+pub(crate) fn bearer(token: &str) -> Option<HeaderValue> {
+    let mut value = HeaderValue::from_str(&format!("Bearer {token}")).ok()?;
+    value.set_sensitive(true);
+    Some(value)
+}
+
+/// The standard base64 encoding with padding, which is all we need here.
+fn base64(input: &[u8]) -> String {
+    const TABLE: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut out = String::with_capacity(input.len().div_ceil(3) * 4);
+    for chunk in input.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(TABLE[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn encode() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foob"), "Zm9vYg==");
+        assert_eq!(
+            base64(b"Aladdin:open sesame"),
+            "QWxhZGRpbjpvcGVuIHNlc2FtZQ=="
+        );
+
+        let value = basic("Aladdin", "open sesame");
+        assert_eq!(value, "Basic QWxhZGRpbjpvcGVuIHNlc2FtZQ==");
+        assert!(value.is_sensitive());
+    }
+
+    #[test]
+    fn bearer_token() {
+        let value = bearer("abc.def");
+        assert!(value.is_some());
+        let value = value.unwrap();
+        assert_eq!(value, "Bearer abc.def");
+        assert!(value.is_sensitive());
+
+        assert!(bearer("line\nbreak").is_none());
+    }
+}
@@ -0,0 +1,151 @@
+use http::uri::{Authority, Scheme};
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The points of an upstream on the ring of [`Balance::ConsistentHash`].
+const VIRTUAL_NODES: usize = 160;
+
+/// How [`Builder::balance_with()`](crate::ReusedServiceBuilder::balance_with) selects an upstream
+/// for a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum Balance {
+    /// One after another.
+    #[default]
+    RoundRobin,
+    /// By the hash of the request path, so that the same path always goes to the same upstream.
+    ///
+    /// The upstreams are placed on a ring at many points each, and a path goes to the upstream
+    /// at the next point from its hash. Adding or removing an upstream thus moves only the paths
+    /// of its share to or from the others. The hash is FNV-1a, fixed across releases and
+    /// platforms, so every replica of a proxy selects the same upstream for a path.
+    ///
+    /// The path is the one requested by the client, before rewritten: the upstream is selected
+    /// first, since the rewriter is given the upstream's authority, *e.g.* for
+    /// [`RewriteHost`](crate::rewrite::RewriteHost).
+    ConsistentHash,
+}
+
+/// Selects an upstream by a [`Balance`].
+#[derive(Debug)]
+pub(crate) struct Balancer {
+    upstreams: Vec<(Scheme, Authority)>,
+    next: AtomicUsize,
+    /// The points of [`Balance::ConsistentHash`] and the indices of their upstreams, sorted.
+    ring: Vec<(u64, usize)>,
+}
+
+impl Balancer {
+    /// `upstreams` must not be empty.
+    pub(crate) fn new(upstreams: Vec<(Scheme, Authority)>, balance: Balance) -> Self {
+        debug_assert!(!upstreams.is_empty());
+        let mut ring = Vec::new();
+        if balance == Balance::ConsistentHash {
+            for (i, (scheme, authority)) in upstreams.iter().enumerate() {
+                for node in 0..VIRTUAL_NODES {
+                    let node = (node as u64).to_le_bytes();
+                    let key = [
+                        scheme.as_str().as_bytes(),
+                        authority.as_str().as_bytes(),
+                        &node,
+                    ];
+                    ring.push((hash(&key), i));
+                }
+            }
+            ring.sort_unstable();
+        }
+        Self {
+            upstreams,
+            next: AtomicUsize::new(0),
+            ring,
+        }
+    }
+
+    pub(crate) fn select(&self, path: &str) -> (&Scheme, &Authority) {
+        let i = if self.ring.is_empty() {
+            self.next.fetch_add(1, Ordering::Relaxed) % self.upstreams.len()
+        } else {
+            let point = hash(&[path.as_bytes()]);
+            let next = self.ring.partition_point(|&(p, _)| p < point);
+            self.ring[next % self.ring.len()].1
+        };
+        let (scheme, authority) = &self.upstreams[i];
+        (scheme, authority)
+    }
+}
+
+/// 64-bit FNV-1a of `parts`, each followed by a `0xff` so that the boundaries count, with the
+/// finalizer of MurmurHash3 to spread similar paths over the ring.
+fn hash(parts: &[&[u8]]) -> u64 {
+    const OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+    let mut h = OFFSET;
+    for byte in parts.iter().flat_map(|part| part.iter().chain([&0xff])) {
+        h ^= u64::from(*byte);
+        h = h.wrapping_mul(PRIME);
+    }
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    h ^ (h >> 33)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn upstreams(n: usize) -> Vec<(Scheme, Authority)> {
+        (0..n)
+            .map(|i| (Scheme::HTTP, format!("upstream{i}").parse().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn stable_hash() {
+        // Pinned, since a change would remap the paths of the deployed rings.
+        assert_eq!(hash(&[b"/items/1"]), 0xce99_b19e_2fb8_a418);
+        assert_ne!(hash(&[b"ab", b"c"]), hash(&[b"a", b"bc"]));
+    }
+
+    #[test]
+    fn round_robin() {
+        let balancer = Balancer::new(upstreams(2), Balance::RoundRobin);
+        let hosts: Vec<_> = (0..4)
+            .map(|_| balancer.select("/").1.as_str().to_owned())
+            .collect();
+        assert_eq!(hosts, ["upstream0", "upstream1", "upstream0", "upstream1"]);
+    }
+
+    #[test]
+    fn consistent_hash() {
+        let paths: Vec<_> = (0..1000).map(|i| format!("/items/{i}")).collect();
+        let select = |balancer: &Balancer| -> Vec<String> {
+            paths
+                .iter()
+                .map(|path| balancer.select(path).1.as_str().to_owned())
+                .collect()
+        };
+        let four = Balancer::new(upstreams(4), Balance::ConsistentHash);
+        let selected = select(&four);
+        assert_eq!(select(&four), selected);
+        for i in 0..4 {
+            let share = selected
+                .iter()
+                .filter(|host| **host == format!("upstream{i}"))
+                .count();
+            assert!((150..350).contains(&share), "{share}");
+        }
+
+        // Only the paths moving to the new upstream are remapped, about a fifth of them.
+        let five = Balancer::new(upstreams(5), Balance::ConsistentHash);
+        let remapped: Vec<_> = selected
+            .iter()
+            .zip(select(&five))
+            .filter(|(four, five)| **four != *five)
+            .map(|(_, five)| five)
+            .collect();
+        assert!(remapped.iter().all(|host| host == "upstream4"));
+        assert!((100..300).contains(&remapped.len()), "{}", remapped.len());
+    }
+}
@@ -0,0 +1,139 @@
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// Circuit breaker shared by the services built from the same builder.
+#[derive(Debug)]
+pub(crate) struct Breaker {
+    threshold: usize,
+    cooldown: Duration,
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Closed {
+        failures: usize,
+    },
+    /// Requests fail fast until the instant, after which one request is let through as a probe.
+    Open {
+        until: Instant,
+    },
+    /// The probe is in flight.
+    HalfOpen,
+}
+
+/// A request let through by a [`Breaker`], whose result should be [`record`](Self::record)ed.
+pub(crate) struct Admission {
+    breaker: Arc<Breaker>,
+    probe: bool,
+}
+
+impl Breaker {
+    pub(crate) fn new(threshold: usize, cooldown: Duration) -> Self {
+        Self {
+            threshold: threshold.max(1),
+            cooldown,
+            state: Mutex::new(State::Closed { failures: 0 }),
+        }
+    }
+
+    /// Lets a request through, or returns `None` if the circuit is open.
+    pub(crate) fn admit(self: &Arc<Self>) -> Option<Admission> {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let probe = match *state {
+            State::Closed { .. } => false,
+            State::Open { until } if until <= Instant::now() => {
+                *state = State::HalfOpen;
+                true
+            }
+            State::Open { .. } | State::HalfOpen => return None,
+        };
+        Some(Admission {
+            breaker: self.clone(),
+            probe,
+        })
+    }
+}
+
+impl Admission {
+    /// Records the result of the request.
+    pub(crate) fn record(mut self, success: bool) {
+        let breaker = &self.breaker;
+        let mut state = breaker.state.lock().unwrap_or_else(PoisonError::into_inner);
+        *state = match (*state, self.probe) {
+            (State::HalfOpen, true) | (State::Closed { .. }, false) if success => {
+                State::Closed { failures: 0 }
+            }
+            (State::HalfOpen, true) => State::Open {
+                until: Instant::now() + breaker.cooldown,
+            },
+            (State::Closed { failures }, false) if failures + 1 >= breaker.threshold => {
+                State::Open {
+                    until: Instant::now() + breaker.cooldown,
+                }
+            }
+            (State::Closed { failures }, false) => State::Closed {
+                failures: failures + 1,
+            },
+            // A request let through before the circuit opened.
+            (state, _) => state,
+        };
+        drop(state);
+        // The result is recorded, so that `Drop` has nothing to do.
+        self.probe = false;
+    }
+}
+
+impl Drop for Admission {
+    /// A probe dropped without the result, *e.g.* cancelled, lets the next request be a probe.
+    fn drop(&mut self) {
+        if self.probe {
+            let mut state = self
+                .breaker
+                .state
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            if *state == State::HalfOpen {
+                *state = State::Open {
+                    until: Instant::now(),
+                };
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn state() {
+        let breaker = Arc::new(Breaker::new(2, Duration::from_millis(50)));
+        let admit = || breaker.admit();
+        let is_open = || matches!(*breaker.state.lock().unwrap(), State::Open { .. });
+
+        admit().unwrap().record(false);
+        admit().unwrap().record(true);
+        admit().unwrap().record(false);
+        assert!(!is_open());
+        admit().unwrap().record(false);
+        assert!(is_open());
+        assert!(admit().is_none());
+
+        // Half-open: the probe only, and it fails.
+        std::thread::sleep(Duration::from_millis(60));
+        let probe = admit().unwrap();
+        assert!(admit().is_none());
+        probe.record(false);
+        assert!(admit().is_none());
+
+        // A cancelled probe, then the recovery.
+        std::thread::sleep(Duration::from_millis(60));
+        drop(admit().unwrap());
+        let probe = admit().unwrap();
+        probe.record(true);
+        assert!(!is_open());
+        admit().unwrap().record(false);
+        assert!(admit().is_some());
+    }
+}
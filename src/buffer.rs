@@ -0,0 +1,213 @@
+use crate::reused::ReusedService;
+use crate::Error;
+
+use http::{Request, Response};
+
+use hyper::body::Body;
+
+use tower::buffer::Buffer;
+use tower_service::Service;
+
+use std::convert::Infallible;
+use std::future::{poll_fn, Future};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// [`ReusedService`] run by a single task behind a [`Buffer`], see
+/// [`ReusedService::buffered()`].
+///
+/// The `Service` types are the same as the ones of `ReusedService`, so that this can be routed in
+/// axum as well.
+pub struct BufferedService<Pr, C, B = Body>
+where
+    ReusedService<Pr, C, B>: Service<Request<B>>,
+{
+    inner: Buffer<ReusedService<Pr, C, B>, Request<B>>,
+    // The buffer failed in `poll_ready()`, so that the next `call()` fails instead.
+    closed: bool,
+    // `poll_ready()` reserved a slot of the buffer for the next `call()`.
+    ready: bool,
+}
+
+impl<Pr, C, B> Clone for BufferedService<Pr, C, B>
+where
+    ReusedService<Pr, C, B>: Service<Request<B>>,
+{
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            closed: false,
+            ready: false,
+        }
+    }
+}
+
+impl<Pr, C, B> std::fmt::Debug for BufferedService<Pr, C, B>
+where
+    ReusedService<Pr, C, B>: Service<Request<B>>,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BufferedService")
+            .field("closed", &self.closed)
+            .field("ready", &self.ready)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<Pr, C, B> ReusedService<Pr, C, B> {
+    /// Moves the service into a task driving it behind a [`Buffer`] of `bound` requests, and
+    /// returns a handle to it.
+    ///
+    /// The handles are cheap to clone and share the rewriter, which does not have to be `Clone`.
+    /// In turn every request goes through a channel to the task, adding a queueing delay, and
+    /// the rewriter runs there one request at a time. When `bound` requests are queued,
+    /// [`poll_ready()`](Service::poll_ready) of a handle waits for a slot. A request called
+    /// without polling readiness first waits for a slot in the returned future instead.
+    ///
+    /// If the task is gone, *e.g.* because the runtime is shutting down, a request results in
+    /// [`Error::Overloaded`]. This must be called in a Tokio runtime.
+    ///
+    /// ```
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// use reverse_proxy_service::ReplaceAll;
+    ///
+    /// let svc = reverse_proxy_service::builder_http::<hyper::Body, _>("example.com")
+    ///     .unwrap()
+    ///     .build(ReplaceAll("foo", "bar"))
+    ///     .buffered(1024);
+    /// let _worker = svc.clone();
+    /// # }
+    /// ```
+    pub fn buffered(self, bound: usize) -> BufferedService<Pr, C, B>
+    where
+        Self: Service<Request<B>, Response = Result<Response<Body>, Error>, Error = Infallible>
+            + Send
+            + 'static,
+        <Self as Service<Request<B>>>::Future: Send,
+        B: Send + 'static,
+    {
+        BufferedService {
+            inner: Buffer::new(self, bound),
+            closed: false,
+            ready: false,
+        }
+    }
+}
+
+impl<Pr, C, B> Service<Request<B>> for BufferedService<Pr, C, B>
+where
+    ReusedService<Pr, C, B>:
+        Service<Request<B>, Response = Result<Response<Body>, Error>, Error = Infallible>,
+    <ReusedService<Pr, C, B> as Service<Request<B>>>::Future: Send + 'static,
+    Pr: 'static,
+    C: 'static,
+    B: Send + 'static,
+{
+    type Response = Result<Response<Body>, Error>;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Infallible>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        match self.inner.poll_ready(cx) {
+            Poll::Ready(Err(e)) => {
+                log::error!("Buffered service failed: {e}");
+                self.closed = true;
+                Poll::Ready(Ok(()))
+            }
+            Poll::Ready(Ok(())) => {
+                self.ready = true;
+                Poll::Ready(Ok(()))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn call(&mut self, req: Request<B>) -> Self::Future {
+        if std::mem::take(&mut self.closed) {
+            return Box::pin(async { Ok(Err(Error::Overloaded)) });
+        }
+        // `Buffer::call()` panics without a slot reserved by `poll_ready()`.
+        let res: Pin<Box<dyn Future<Output = _> + Send>> = if std::mem::take(&mut self.ready) {
+            Box::pin(self.inner.call(req))
+        } else {
+            let mut inner = self.inner.clone();
+            Box::pin(async move {
+                poll_fn(|cx| inner.poll_ready(cx)).await?;
+                inner.call(req).await
+            })
+        };
+        Box::pin(async move {
+            match res.await {
+                Ok(res) => Ok(res),
+                Err(e) => {
+                    log::error!("Buffered service failed: {e}");
+                    Ok(Err(Error::Overloaded))
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{builder_http, test_helper, Identity};
+
+    use http::StatusCode;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn buffered() {
+        let addr = test_helper::serve(|req| async move {
+            Ok(Response::new(Body::from(req.uri().path().to_owned())))
+        });
+        let builder = builder_http(addr.to_string());
+        assert!(builder.is_ok());
+        let svc = builder.unwrap().build(Identity).buffered(4);
+
+        let handles = (0..16).map(|i| {
+            let mut svc = svc.clone();
+            tokio::spawn(async move {
+                let req = Request::builder()
+                    .uri(format!("https://test.com/{i}"))
+                    .body(Body::empty())
+                    .unwrap();
+                poll_fn(|cx| svc.poll_ready(cx)).await.unwrap();
+                let res = svc.call(req).await.unwrap().unwrap();
+                assert_eq!(res.status(), StatusCode::OK);
+                let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+                assert_eq!(body, format!("/{i}"));
+            })
+        });
+        let handles: Vec<_> = handles.collect();
+        for handle in handles {
+            assert!(handle.await.is_ok());
+        }
+    }
+    #[tokio::test]
+    async fn call_without_poll_ready() {
+        let addr = test_helper::serve(|req| async move {
+            Ok(Response::new(Body::from(req.uri().path().to_owned())))
+        });
+        let builder = builder_http(addr.to_string());
+        assert!(builder.is_ok());
+        let mut svc = builder.unwrap().build(Identity).buffered(1);
+
+        // More requests than the bound, none of them polling readiness.
+        let futures: Vec<_> = (0..4)
+            .map(|i| {
+                let req = Request::builder()
+                    .uri(format!("https://test.com/{i}"))
+                    .body(Body::empty())
+                    .unwrap();
+                svc.call(req)
+            })
+            .collect();
+        for (i, fut) in futures.into_iter().enumerate() {
+            let res = fut.await.unwrap().unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+            let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+            assert_eq!(body, format!("/{i}"));
+        }
+    }
+}
@@ -0,0 +1,385 @@
+use crate::future::collect;
+use crate::Error;
+
+use http::header::{self, HeaderMap, HeaderName, HeaderValue};
+use http::{Method, Request, Response, StatusCode, Uri};
+
+use hyper::body::{Body, Bytes, HttpBody};
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use tokio::sync::Semaphore;
+
+/// Responses larger than this are not cached.
+pub(crate) const MAX_BODY: usize = 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct Key {
+    method: Method,
+    uri: Uri,
+    vary: Vec<Option<HeaderValue>>,
+    /// Whether the request has `Authorization` which is not in `vary`.
+    authorized: bool,
+}
+
+struct Entry {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+    stored: Instant,
+    expires: Instant,
+}
+
+/// In-memory cache of `GET` responses, shared by the services built from the same builder.
+pub(crate) struct Cache {
+    capacity: usize,
+    default_ttl: Duration,
+    entries: Mutex<HashMap<Key, Entry>>,
+}
+
+impl fmt::Debug for Cache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Cache")
+            .field("capacity", &self.capacity)
+            .field("default_ttl", &self.default_ttl)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Cache {
+    pub(crate) fn new(capacity: usize, default_ttl: Duration) -> Self {
+        Self {
+            capacity,
+            default_ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The cached response, or `304 Not Modified` if the conditional headers in `headers` match it.
+    pub(crate) fn get(&self, key: &Key, headers: &HeaderMap) -> Option<Response<Body>> {
+        let mut entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        let now = Instant::now();
+        let entry = entries.get(key)?;
+        if entry.expires <= now {
+            entries.remove(key);
+            return None;
+        }
+        let mut res = if is_not_modified(headers, &entry.headers) {
+            let mut res = Response::new(Body::empty());
+            *res.status_mut() = StatusCode::NOT_MODIFIED;
+            for name in NOT_MODIFIED_HEADERS {
+                for value in entry.headers.get_all(&name) {
+                    res.headers_mut().append(name.clone(), value.clone());
+                }
+            }
+            res
+        } else {
+            let mut res = Response::new(Body::from(entry.body.clone()));
+            *res.status_mut() = entry.status;
+            *res.headers_mut() = entry.headers.clone();
+            res
+        };
+        let age = (now - entry.stored).as_secs();
+        res.headers_mut().insert(header::AGE, age.into());
+        Some(res)
+    }
+
+    /// Stores `res` if it is cacheable, and returns it with the body buffered.
+    ///
+    /// The returned body is reserved against `budget`, if any, as by [`collect()`], while the copy
+    /// kept in the cache is not.
+    pub(crate) async fn store(
+        &self,
+        key: Key,
+        res: Response<Body>,
+        vary: &[HeaderName],
+        budget: Option<&Arc<Semaphore>>,
+    ) -> Result<Response<Body>, Error> {
+        let ttl = match self.ttl(&res, vary, key.authorized) {
+            Some(ttl) => ttl,
+            None => return Ok(res),
+        };
+        let (mut parts, body) = res.into_parts();
+        let body = collect(body, MAX_BODY, budget).await?;
+        parts.headers.remove(header::TRANSFER_ENCODING);
+        parts
+            .headers
+            .insert(header::CONTENT_LENGTH, body.len().into());
+
+        let now = Instant::now();
+        let entry = Entry {
+            status: parts.status,
+            headers: parts.headers.clone(),
+            // A clone would hold the reservation as long as the entry lives.
+            body: match budget {
+                Some(_) => Bytes::copy_from_slice(&body),
+                None => body.clone(),
+            },
+            stored: now,
+            expires: now + ttl,
+        };
+        let mut entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        if entries.len() >= self.capacity && !entries.contains_key(&key) {
+            entries.retain(|_, entry| entry.expires > now);
+        }
+        if entries.len() >= self.capacity && !entries.contains_key(&key) {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.expires)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+            }
+        }
+        if self.capacity > 0 {
+            entries.insert(key, entry);
+        }
+
+        Ok(Response::from_parts(parts, body.into()))
+    }
+
+    /// How long `res` is fresh, or `None` if it is not cacheable.
+    ///
+    /// The response to an `authorized` request is cacheable only if it is marked `public` or has
+    /// `s-maxage`, as a shared cache must not serve it to other users otherwise.
+    fn ttl(&self, res: &Response<Body>, vary: &[HeaderName], authorized: bool) -> Option<Duration> {
+        if res.status() != StatusCode::OK
+            || res.headers().contains_key(header::SET_COOKIE)
+            || res
+                .body()
+                .size_hint()
+                .upper()
+                .is_none_or(|len| len > MAX_BODY as u64)
+        {
+            return None;
+        }
+        // Other `Vary` headers than the configured ones would mix up the variants.
+        for value in res.headers().get_all(header::VARY) {
+            for name in value.to_str().ok()?.split(',') {
+                let name = HeaderName::try_from(name.trim()).ok()?;
+                if !vary.contains(&name) {
+                    return None;
+                }
+            }
+        }
+
+        let directives = cache_control(res.headers());
+        if authorized
+            && !directives
+                .iter()
+                .any(|d| d == "public" || d.starts_with("s-maxage="))
+        {
+            return None;
+        }
+        let mut max_age = None;
+        for directive in &directives {
+            match directive.split_once('=') {
+                Some(("s-maxage", secs)) => {
+                    max_age = Some(secs.trim_matches('"').parse().ok()?);
+                    break;
+                }
+                Some(("max-age", secs)) => max_age = Some(secs.trim_matches('"').parse().ok()?),
+                None if ["no-store", "no-cache", "private"].contains(&directive.as_str()) => {
+                    return None;
+                }
+                _ => {}
+            }
+        }
+        match max_age {
+            Some(0) => None,
+            Some(secs) => Some(Duration::from_secs(secs)),
+            None => Some(self.default_ttl),
+        }
+    }
+}
+
+/// The key of a (rewritten) request, or `None` if the request must not be served from the cache,
+/// nor [coalesced](crate::ReusedServiceBuilder::singleflight).
+///
+/// A request with `Cookie` is not cached unless the header is in `vary`. One with `Authorization`
+/// is cached apart from the others, see [`Cache::ttl()`].
+pub(crate) fn key<B>(req: &Request<B>, vary: &[HeaderName]) -> Option<Key> {
+    let personal = |name| req.headers().contains_key(&name) && !vary.contains(&name);
+    if req.method() != Method::GET || personal(header::COOKIE) {
+        return None;
+    }
+    let directives = cache_control(req.headers());
+    if directives
+        .iter()
+        .any(|d| d == "no-store" || d == "no-cache")
+    {
+        return None;
+    }
+    Some(Key {
+        method: req.method().clone(),
+        uri: req.uri().clone(),
+        vary: vary
+            .iter()
+            .map(|name| req.headers().get(name).cloned())
+            .collect(),
+        authorized: personal(header::AUTHORIZATION),
+    })
+}
+
+/// The headers of a cached response copied into `304 Not Modified`.
+const NOT_MODIFIED_HEADERS: [HeaderName; 7] = [
+    header::CACHE_CONTROL,
+    header::CONTENT_LOCATION,
+    header::DATE,
+    header::ETAG,
+    header::EXPIRES,
+    header::LAST_MODIFIED,
+    header::VARY,
+];
+
+/// Whether the request with `req` headers is satisfied by `304 Not Modified` for a response with
+/// `res` headers.
+///
+/// `If-None-Match` is compared by the weak comparison. Without it, `If-Modified-Since` must be
+/// the same as `Last-Modified` byte by byte, since dates are not parsed.
+fn is_not_modified(req: &HeaderMap, res: &HeaderMap) -> bool {
+    fn opaque(tag: &[u8]) -> &[u8] {
+        tag.strip_prefix(b"W/").unwrap_or(tag)
+    }
+
+    if let Some(tags) = req.get(header::IF_NONE_MATCH) {
+        let etag = match res.get(header::ETAG) {
+            Some(etag) => opaque(etag.as_bytes()),
+            None => return tags == "*",
+        };
+        return tags
+            .as_bytes()
+            .split(|&b| b == b',')
+            .map(|tag| opaque(tag.trim_ascii()))
+            .any(|tag| tag == b"*" || tag == etag);
+    }
+    match (
+        req.get(header::IF_MODIFIED_SINCE),
+        res.get(header::LAST_MODIFIED),
+    ) {
+        (Some(since), Some(modified)) => since == modified,
+        _ => false,
+    }
+}
+
+/// The directives of `Cache-Control`, in lowercase.
+fn cache_control(headers: &HeaderMap) -> Vec<String> {
+    headers
+        .get_all(header::CACHE_CONTROL)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|directive| directive.trim().to_ascii_lowercase())
+        .filter(|directive| !directive.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn response(headers: &[(&str, &str)]) -> Response<Body> {
+        let mut res = Response::builder();
+        for (name, value) in headers {
+            res = res.header(*name, *value);
+        }
+        res.body(Body::from("ok")).unwrap()
+    }
+
+    #[test]
+    fn ttl() {
+        let cache = Cache::new(8, Duration::from_secs(10));
+        let vary = [header::ACCEPT_LANGUAGE];
+        let ttl = |headers| cache.ttl(&response(headers), &vary, false);
+
+        assert_eq!(ttl(&[]), Some(Duration::from_secs(10)));
+        assert_eq!(
+            ttl(&[("cache-control", "public, max-age=60")]),
+            Some(Duration::from_secs(60))
+        );
+        assert_eq!(
+            ttl(&[("cache-control", "s-maxage=5, max-age=60")]),
+            Some(Duration::from_secs(5))
+        );
+        assert_eq!(ttl(&[("cache-control", "max-age=0")]), None);
+        assert_eq!(ttl(&[("cache-control", "No-Store")]), None);
+        assert_eq!(ttl(&[("cache-control", "private")]), None);
+        assert_eq!(ttl(&[("set-cookie", "a=b")]), None);
+        assert_eq!(
+            ttl(&[("vary", "Accept-Language")]),
+            Some(Duration::from_secs(10))
+        );
+        assert_eq!(ttl(&[("vary", "accept-language, cookie")]), None);
+        assert_eq!(ttl(&[("vary", "*")]), None);
+
+        let authorized = |headers| cache.ttl(&response(headers), &vary, true);
+        assert_eq!(authorized(&[]), None);
+        assert_eq!(authorized(&[("cache-control", "max-age=60")]), None);
+        assert_eq!(
+            authorized(&[("cache-control", "public, max-age=60")]),
+            Some(Duration::from_secs(60))
+        );
+        assert_eq!(
+            authorized(&[("cache-control", "s-maxage=5")]),
+            Some(Duration::from_secs(5))
+        );
+    }
+
+    #[tokio::test]
+    async fn personal() {
+        let cache = Cache::new(8, Duration::from_secs(10));
+        let request = |name, value| Request::get("/").header(name, value).body(()).unwrap();
+
+        assert!(key(&request(header::COOKIE, "id=1"), &[]).is_none());
+        assert!(key(&request(header::COOKIE, "id=1"), &[header::COOKIE]).is_some());
+
+        let alice = key(&request(header::AUTHORIZATION, "Basic YWxpY2U6"), &[]).unwrap();
+        let bob = key(&request(header::AUTHORIZATION, "Basic Ym9iOg=="), &[]).unwrap();
+        let res = cache.store(alice.clone(), response(&[]), &[], None).await;
+        assert!(res.is_ok());
+        assert!(cache.get(&alice, &HeaderMap::new()).is_none());
+        assert!(cache.get(&bob, &HeaderMap::new()).is_none());
+
+        let anonymous = key(&Request::get("/").body(()).unwrap(), &[]).unwrap();
+        let res = cache
+            .store(anonymous.clone(), response(&[]), &[], None)
+            .await;
+        assert!(res.is_ok());
+        assert!(cache.get(&anonymous, &HeaderMap::new()).is_some());
+        assert!(cache.get(&bob, &HeaderMap::new()).is_none());
+    }
+
+    #[test]
+    fn not_modified() {
+        let headers = |pairs: &[(HeaderName, &'static str)]| {
+            let mut headers = HeaderMap::new();
+            for (name, value) in pairs {
+                headers.insert(name, HeaderValue::from_static(value));
+            }
+            headers
+        };
+        let res = headers(&[
+            (header::ETAG, "W/\"v1\""),
+            (header::LAST_MODIFIED, "Wed, 21 Oct 2015 07:28:00 GMT"),
+        ]);
+
+        let req = headers(&[(header::IF_NONE_MATCH, "\"v0\", \"v1\"")]);
+        assert!(is_not_modified(&req, &res));
+        let req = headers(&[(header::IF_NONE_MATCH, "*")]);
+        assert!(is_not_modified(&req, &res));
+        let req = headers(&[(header::IF_NONE_MATCH, "\"v0\"")]);
+        assert!(!is_not_modified(&req, &res));
+
+        let req = headers(&[(header::IF_MODIFIED_SINCE, "Wed, 21 Oct 2015 07:28:00 GMT")]);
+        assert!(is_not_modified(&req, &res));
+        let req = headers(&[
+            (header::IF_NONE_MATCH, "\"v0\""),
+            (header::IF_MODIFIED_SINCE, "Wed, 21 Oct 2015 07:28:00 GMT"),
+        ]);
+        assert!(!is_not_modified(&req, &res));
+        assert!(!is_not_modified(&HeaderMap::new(), &res));
+    }
+}
@@ -6,6 +6,10 @@ pub use hyper::client::{Builder, Client};
 
 use hyper::client::connect::Connect;
 pub use hyper::client::connect::HttpConnector;
+use hyper::rt::Executor;
+
+pub use hyper::client::connect::dns::{GaiResolver, Name};
+use tower_service::Service;
 
 #[cfg(feature = "https")]
 #[cfg_attr(docsrs, doc(cfg(feature = "https")))]
@@ -15,15 +19,56 @@ pub use hyper_tls::HttpsConnector;
 #[cfg_attr(docsrs, doc(cfg(feature = "rustls")))]
 pub use hyper_rustls::HttpsConnector as RustlsConnector;
 
+#[cfg(feature = "__rustls")]
+use hyper_rustls::builderstates::WantsSchemes;
+/// Re-export of the `rustls` crate, to prepare a [`ClientConfig`](rustls::ClientConfig) for
+/// [`rustls_with_config()`].
+#[cfg(feature = "__rustls")]
+#[cfg_attr(docsrs, doc(cfg(feature = "rustls")))]
+pub use rustls;
+
+pub use crate::family::{FamilyConnector, IpFamily};
+pub use crate::pool::{CountedStream, CountingConnector, PoolStats};
+
+#[cfg(feature = "proxy-protocol")]
+#[cfg_attr(docsrs, doc(cfg(feature = "proxy-protocol")))]
+pub use crate::proxy_protocol::{ProxyProtocol, ProxyProtocolConnector};
+
 #[cfg(feature = "nativetls")]
 #[cfg_attr(docsrs, doc(cfg(feature = "nativetls")))]
 pub use hyper_tls::HttpsConnector as NativeTlsConnector;
 
+#[cfg(feature = "http3")]
+#[cfg_attr(docsrs, doc(cfg(feature = "http3")))]
+pub use crate::http3::Http3Client;
+
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::time::Duration;
+
+type BoxErr = Box<dyn std::error::Error + Send + Sync>;
+
 /// Default [`Builder`].
 pub fn builder() -> Builder {
     Builder::default()
 }
 
+/// [`Builder`] spawning the connection tasks on `exec`, instead of Tokio.
+///
+/// This is for other runtimes than Tokio. Note that the connector still has to work on the
+/// runtime; [`HttpConnector`] and the TLS connectors here rely on Tokio's I/O. Pass the same
+/// executor to [`with_executor()`](crate::ReusedServiceBuilder::with_executor) for the tasks of
+/// the services.
+pub fn builder_with_executor<E>(exec: E) -> Builder
+where
+    E: Executor<Pin<Box<dyn Future<Output = ()> + Send>>> + Send + Sync + 'static,
+{
+    let mut builder = Builder::default();
+    builder.executor(exec);
+    builder
+}
+
 /// Same as [`Client::new()`], except for the `B` parameter.
 pub fn http_default<B>() -> Client<HttpConnector, B>
 where
@@ -33,6 +78,119 @@ where
     Builder::default().build_http()
 }
 
+/// Same as [`http_default()`], except that the connections originate from the `local` address.
+///
+/// See [`HttpConnector::set_local_address()`].
+pub fn http_bound<B>(local: IpAddr) -> Client<HttpConnector, B>
+where
+    B: HttpBody + Send,
+    B::Data: Send,
+{
+    Builder::default().build(http_connector(local))
+}
+
+fn http_connector(local: IpAddr) -> HttpConnector {
+    let mut conn = HttpConnector::new();
+    conn.set_local_address(Some(local));
+    conn
+}
+
+/// Same as [`http_default()`], except that TCP keepalive probes are sent after the connection is
+/// idle for `time`, so that idle connections in the pool survive NATs and firewalls.
+///
+/// See [`HttpConnector::set_keepalive()`]. The interval and the number of the probes are left to
+/// the OS, since `HttpConnector` does not configure them.
+pub fn http_keepalive<B>(time: Duration) -> Client<HttpConnector, B>
+where
+    B: HttpBody + Send,
+    B::Data: Send,
+{
+    Builder::default().build(keepalive_connector(time))
+}
+
+fn keepalive_connector(time: Duration) -> HttpConnector {
+    let mut conn = HttpConnector::new();
+    conn.set_keepalive(Some(time));
+    conn
+}
+
+/// Same as [`http_default()`], except that `TCP_NODELAY` is set to `nodelay`.
+///
+/// See [`HttpConnector::set_nodelay()`]. hyper leaves it unset by default, so that Nagle's
+/// algorithm is enabled; setting it reduces the latency of small writes.
+pub fn http_nodelay<B>(nodelay: bool) -> Client<HttpConnector, B>
+where
+    B: HttpBody + Send,
+    B::Data: Send,
+{
+    Builder::default().build(nodelay_connector(nodelay))
+}
+
+fn nodelay_connector(nodelay: bool) -> HttpConnector {
+    let mut conn = HttpConnector::new();
+    conn.set_nodelay(nodelay);
+    conn
+}
+
+/// Same as [`http_default()`], except that the connections are of `family`, and that the happy
+/// eyeballs timeout is `happy_eyeballs`.
+///
+/// With [`IpFamily::V4`] or [`IpFamily::V6`], the addresses of the other family are dropped from
+/// the resolved ones, and an IP literal of the other family fails, see [`FamilyConnector`]; a name
+/// without an address of the family fails to connect. This holds whether `happy_eyeballs` is
+/// `Some` or `None`.
+///
+/// With [`IpFamily::Any`], a name resolved into both IPv4 and IPv6 addresses is first tried by the
+/// family of the first address, in the order of the resolver, and then by the other family also
+/// if no connection is established in `happy_eyeballs` (hyper defaults to 300 ms). `None`
+/// disables the fallback, so that the addresses are tried one by one. See
+/// [`HttpConnector::set_happy_eyeballs_timeout()`]. To prefer a family but fall back to the
+/// other, sort the addresses by your own resolver with [`http_with_resolver()`].
+pub fn http_with_family<B>(
+    family: IpFamily,
+    happy_eyeballs: Option<Duration>,
+) -> Client<FamilyConnector, B>
+where
+    B: HttpBody + Send,
+    B::Data: Send,
+{
+    Builder::default().build(FamilyConnector::new(family, happy_eyeballs))
+}
+
+/// Same as [`http_default()`], except that the names are resolved by `resolver` instead of
+/// [`GaiResolver`], *e.g.* to pin a hostname to a specific IP.
+///
+/// A resolver is a [`Service<Name>`] returning the addresses to connect to; the port of each
+/// address is replaced with the one of the URI.
+pub fn http_with_resolver<R, B>(resolver: R) -> Client<HttpConnector<R>, B>
+where
+    R: Service<Name> + Clone + Send + Sync + 'static,
+    R::Response: Iterator<Item = SocketAddr>,
+    R::Error: Into<BoxErr>,
+    R::Future: Send,
+    B: HttpBody + Send,
+    B::Data: Send,
+{
+    Builder::default().build(HttpConnector::new_with_resolver(resolver))
+}
+
+/// Same as [`http_default()`], except that each connection starts with a PROXY protocol header
+/// of `version`, see [`ProxyProtocolConnector`].
+///
+/// Idle connections are not kept in the pool, so that every request opens a new connection with
+/// its own client address.
+#[cfg(feature = "proxy-protocol")]
+#[cfg_attr(docsrs, doc(cfg(feature = "proxy-protocol")))]
+pub fn http_proxy_protocol<B>(version: ProxyProtocol) -> Client<ProxyProtocolConnector, B>
+where
+    B: HttpBody + Send,
+    B::Data: Send,
+{
+    Builder::default()
+        .pool_max_idle_per_host(0)
+        .build(ProxyProtocolConnector::new(HttpConnector::new(), version))
+}
+
 /// Alias to [`nativetls_default()`].
 #[cfg(any(feature = "https", feature = "nativetls"))]
 #[cfg_attr(docsrs, doc(cfg(any(feature = "https", feature = "nativetls"))))]
@@ -56,6 +214,34 @@ where
     Builder::default().build(NativeTlsConnector::new())
 }
 
+/// Same as [`nativetls_default()`], except that the connections originate from the `local`
+/// address.
+#[cfg(feature = "nativetls")]
+#[cfg_attr(docsrs, doc(cfg(feature = "nativetls")))]
+pub fn nativetls_bound<B>(local: IpAddr) -> Client<NativeTlsConnector<HttpConnector>, B>
+where
+    B: HttpBody + Send,
+    B::Data: Send,
+{
+    let mut conn = http_connector(local);
+    conn.enforce_http(false);
+    Builder::default().build(NativeTlsConnector::new_with_connector(conn))
+}
+
+/// Same as [`nativetls_default()`], except that `TCP_NODELAY` is set to `nodelay`, see
+/// [`http_nodelay()`].
+#[cfg(feature = "nativetls")]
+#[cfg_attr(docsrs, doc(cfg(feature = "nativetls")))]
+pub fn nativetls_nodelay<B>(nodelay: bool) -> Client<NativeTlsConnector<HttpConnector>, B>
+where
+    B: HttpBody + Send,
+    B::Data: Send,
+{
+    let mut conn = nodelay_connector(nodelay);
+    conn.enforce_http(false);
+    Builder::default().build(NativeTlsConnector::new_with_connector(conn))
+}
+
 /// With the default [`hyper_rustls::HttpsConnector`].
 ///
 /// The config is determined as follows. I think the cert root is similar to the `reqwest` crate.
@@ -90,17 +276,117 @@ where
     B: HttpBody + Send,
     B::Data: Send,
 {
+    let mut conn = HttpConnector::new();
+    conn.enforce_http(false);
+    Builder::default().build(rustls_connector(conn))
+}
+
+/// Same as [`rustls_default()`], except that the connections originate from the `local` address.
+#[cfg(feature = "__rustls")]
+#[cfg_attr(docsrs, doc(cfg(feature = "rustls")))]
+pub fn rustls_bound<B>(local: IpAddr) -> Client<RustlsConnector<HttpConnector>, B>
+where
+    B: HttpBody + Send,
+    B::Data: Send,
+{
+    let mut conn = http_connector(local);
+    conn.enforce_http(false);
+    Builder::default().build(rustls_connector(conn))
+}
+
+/// Same as [`rustls_default()`], except that `TCP_NODELAY` is set to `nodelay`, see
+/// [`http_nodelay()`].
+#[cfg(feature = "__rustls")]
+#[cfg_attr(docsrs, doc(cfg(feature = "rustls")))]
+pub fn rustls_nodelay<B>(nodelay: bool) -> Client<RustlsConnector<HttpConnector>, B>
+where
+    B: HttpBody + Send,
+    B::Data: Send,
+{
+    let mut conn = nodelay_connector(nodelay);
+    conn.enforce_http(false);
+    Builder::default().build(rustls_connector(conn))
+}
+
+/// Same as [`rustls_default()`], except that only the TLS `versions` are enabled, *e.g.*
+/// `&[&rustls::version::TLS13]` to disable TLS 1.2.
+///
+/// The cipher suites and key exchange groups are the safe defaults of `rustls`. To configure them
+/// too, use [`rustls_with_config()`]. Fails if none of the cipher suites supports the versions.
+#[cfg(feature = "__rustls")]
+#[cfg_attr(docsrs, doc(cfg(feature = "rustls")))]
+pub fn rustls_with_versions<B>(
+    versions: &[&'static rustls::SupportedProtocolVersion],
+) -> Result<Client<RustlsConnector<HttpConnector>, B>, rustls::Error>
+where
+    B: HttpBody + Send,
+    B::Data: Send,
+{
+    use hyper_rustls::ConfigBuilderExt;
+
+    let config = rustls::ClientConfig::builder()
+        .with_safe_default_cipher_suites()
+        .with_safe_default_kx_groups()
+        .with_protocol_versions(versions)?;
+    #[cfg(feature = "rustls-webpki-roots")]
+    let config = config.with_webpki_roots();
+    #[cfg(all(not(feature = "rustls-webpki-roots"), feature = "rustls-native-roots"))]
+    let config = config.with_native_roots();
+    Ok(rustls_with_config(config.with_no_client_auth()))
+}
+
+/// Same as [`rustls_default()`], except that the TLS connections are configured by `config`,
+/// *e.g.* to restrict the protocol versions and the cipher suites, or to use custom cert roots.
+///
+/// The ALPN protocols of `config` are overwritten by the `http1` and `rustls-http2` features, as
+/// in [`rustls_default()`].
+#[cfg(feature = "__rustls")]
+#[cfg_attr(docsrs, doc(cfg(feature = "rustls")))]
+pub fn rustls_with_config<B>(
+    config: rustls::ClientConfig,
+) -> Client<RustlsConnector<HttpConnector>, B>
+where
+    B: HttpBody + Send,
+    B::Data: Send,
+{
+    let mut conn = HttpConnector::new();
+    conn.enforce_http(false);
+    let tls = hyper_rustls::HttpsConnectorBuilder::new().with_tls_config(config);
+    Builder::default().build(finish_rustls_connector(tls, conn))
+}
+
+#[cfg(feature = "__rustls")]
+fn rustls_connector(http: HttpConnector) -> RustlsConnector<HttpConnector> {
     let conn = hyper_rustls::HttpsConnectorBuilder::new();
     #[cfg(feature = "rustls-webpki-roots")]
     let conn = conn.with_webpki_roots();
     #[cfg(all(not(feature = "rustls-webpki-roots"), feature = "rustls-native-roots"))]
     let conn = conn.with_native_roots();
+    finish_rustls_connector(conn, http)
+}
+
+#[cfg(feature = "__rustls")]
+fn finish_rustls_connector(
+    conn: hyper_rustls::HttpsConnectorBuilder<WantsSchemes>,
+    http: HttpConnector,
+) -> RustlsConnector<HttpConnector> {
     let conn = conn.https_only();
     #[cfg(feature = "http1")]
     let conn = conn.enable_http1();
     #[cfg(feature = "rustls-http2")]
     let conn = conn.enable_http2();
-    Builder::default().build(conn.build())
+    conn.wrap_connector(http)
+}
+
+/// [`Http3Client`] trusting the roots of the `webpki-roots` crate.
+///
+/// QUIC mandates TLS 1.3, so that only TLS 1.3 is offered, and an upstream speaking HTTP/3 has to
+/// present a certificate valid for its host. The connections are made on a UDP socket bound on
+/// the first request.
+#[cfg(feature = "http3")]
+#[cfg_attr(docsrs, doc(cfg(feature = "http3")))]
+pub fn http3_default() -> Http3Client {
+    Http3Client::webpki_roots()
 }
 
 /// Default builder and given connector.
@@ -112,3 +398,82 @@ where
 {
     Builder::default().build(conn)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use http::Uri;
+    #[cfg(feature = "__rustls")]
+    use hyper::body::Body;
+
+    #[cfg(feature = "__rustls")]
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn nodelay() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let uri = Uri::try_from(format!("http://{}", listener.local_addr().unwrap())).unwrap();
+        for nodelay in [true, false] {
+            let stream = nodelay_connector(nodelay).call(uri.clone()).await;
+            assert!(stream.is_ok());
+            assert_eq!(stream.unwrap().nodelay().unwrap(), nodelay);
+        }
+    }
+
+    #[tokio::test]
+    async fn keepalive() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let uri = Uri::try_from(format!("http://{}", listener.local_addr().unwrap())).unwrap();
+        let stream = keepalive_connector(Duration::from_secs(60))
+            .call(uri.clone())
+            .await;
+        assert!(stream.is_ok());
+        let stream = stream.unwrap();
+        assert!(socket2::SockRef::from(&stream).keepalive().unwrap());
+
+        let stream = HttpConnector::new().call(uri).await;
+        assert!(stream.is_ok());
+        let stream = stream.unwrap();
+        assert!(!socket2::SockRef::from(&stream).keepalive().unwrap());
+    }
+
+    /// The ClientHello sent by `client`.
+    #[cfg(feature = "__rustls")]
+    async fn client_hello(client: Client<RustlsConnector<HttpConnector>, Body>) -> Vec<u8> {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let uri = Uri::try_from(format!(
+            "https://localhost:{}",
+            listener.local_addr().unwrap().port()
+        ));
+        let req = tokio::spawn(client.get(uri.unwrap()));
+
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut hello = vec![0; 4096];
+        let len = stream.read(&mut hello).await.unwrap();
+        hello.truncate(len);
+        drop(stream);
+        assert!(req.await.unwrap().is_err());
+        hello
+    }
+
+    #[cfg(feature = "__rustls")]
+    #[tokio::test]
+    async fn rustls_with_versions() {
+        // `supported_versions` extension with TLS 1.3 and 1.2
+        const BOTH: &[u8] = &[0x00, 0x2b, 0x00, 0x05, 0x04, 0x03, 0x04, 0x03, 0x03];
+        // `supported_versions` extension with TLS 1.3 only
+        const TLS13: &[u8] = &[0x00, 0x2b, 0x00, 0x03, 0x02, 0x03, 0x04];
+        let contains = |hello: &[u8], ext: &[u8]| hello.windows(ext.len()).any(|w| w == ext);
+
+        let hello = client_hello(rustls_default()).await;
+        assert!(contains(&hello, BOTH));
+
+        let client = super::rustls_with_versions(&[&rustls::version::TLS13]);
+        assert!(client.is_ok());
+        let hello = client_hello(client.unwrap()).await;
+        assert!(contains(&hello, TLS13));
+        assert!(!contains(&hello, BOTH));
+    }
+}
@@ -0,0 +1,293 @@
+use crate::config::Config;
+use crate::future::{collect, set_content_length};
+use crate::{BodyTransform, Error};
+
+use http::header::{self, HeaderValue};
+use http::Request;
+
+use hyper::body::{Body, Bytes, HttpBody};
+
+use flate2::write::{GzEncoder, ZlibEncoder};
+use flate2::Compression;
+
+use std::io::Write;
+
+type BoxErr = Box<dyn std::error::Error + Send + Sync>;
+
+/// Bodies smaller than this are sent as they are, since the compression would not pay off.
+pub(crate) const MIN_BYTES: usize = 1024;
+
+/// `Content-Encoding` of the request bodies compressed by
+/// [`Builder::compress_request()`](crate::ReusedServiceBuilder::compress_request).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Encoding {
+    /// `gzip`
+    Gzip,
+    /// `deflate`, *i.e.* the zlib format
+    Deflate,
+}
+
+impl Encoding {
+    fn header_value(self) -> HeaderValue {
+        match self {
+            Self::Gzip => HeaderValue::from_static("gzip"),
+            Self::Deflate => HeaderValue::from_static("deflate"),
+        }
+    }
+
+    fn encode(self, body: &[u8]) -> Vec<u8> {
+        let encoded = match self {
+            Self::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(body).and_then(|_| encoder.finish())
+            }
+            Self::Deflate => {
+                let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(body).and_then(|_| encoder.finish())
+            }
+        };
+        encoded.expect("writing to a Vec never fails")
+    }
+}
+
+/// Compresses the request bodies chunk by chunk in a task spawned on Tokio, and sets
+/// `Content-Encoding`. An empty body, or one already with `Content-Encoding`, is sent as is.
+///
+/// Unlike [`Builder::compress_request()`](crate::ReusedServiceBuilder::compress_request), a body
+/// of any size is compressed without buffering it, and sent without `Content-Length`. The
+/// responses are returned as they are.
+impl BodyTransform for Encoding {
+    fn transform_request(&self, req: Request<Body>) -> Request<Body> {
+        if req.headers().contains_key(header::CONTENT_ENCODING) || req.body().is_end_stream() {
+            return req;
+        }
+        let (mut parts, mut body) = req.into_parts();
+        parts.headers.remove(header::CONTENT_LENGTH);
+        parts
+            .headers
+            .insert(header::CONTENT_ENCODING, self.header_value());
+
+        let mut encoder = StreamEncoder::new(*self);
+        let (mut tx, encoded) = Body::channel();
+        tokio::spawn(async move {
+            while let Some(chunk) = body.data().await {
+                let Ok(chunk) = chunk else {
+                    return tx.abort();
+                };
+                let chunk = encoder.encode(&chunk);
+                if !chunk.is_empty() && tx.send_data(chunk).await.is_err() {
+                    return;
+                }
+            }
+            let _ = tx.send_data(encoder.finish()).await;
+        });
+        Request::from_parts(parts, encoded)
+    }
+}
+
+enum StreamEncoder {
+    Gzip(GzEncoder<Vec<u8>>),
+    Deflate(ZlibEncoder<Vec<u8>>),
+}
+
+impl StreamEncoder {
+    fn new(encoding: Encoding) -> Self {
+        match encoding {
+            Encoding::Gzip => Self::Gzip(GzEncoder::new(Vec::new(), Compression::default())),
+            Encoding::Deflate => {
+                Self::Deflate(ZlibEncoder::new(Vec::new(), Compression::default()))
+            }
+        }
+    }
+
+    /// Compresses `chunk`, and takes the output so far, which may be empty.
+    fn encode(&mut self, chunk: &[u8]) -> Bytes {
+        let out = match self {
+            Self::Gzip(encoder) => encoder.write_all(chunk).map(|_| encoder.get_mut()),
+            Self::Deflate(encoder) => encoder.write_all(chunk).map(|_| encoder.get_mut()),
+        };
+        std::mem::take(out.expect("writing to a Vec never fails")).into()
+    }
+
+    /// Takes the rest of the output.
+    fn finish(self) -> Bytes {
+        let out = match self {
+            Self::Gzip(encoder) => encoder.finish(),
+            Self::Deflate(encoder) => encoder.finish(),
+        };
+        out.expect("writing to a Vec never fails").into()
+    }
+}
+
+/// Compresses the body of `req` by `encoding`, if the size is known to be between [`MIN_BYTES`]
+/// and the replay limit, and it is not encoded yet.
+pub(crate) async fn compress<B>(
+    req: Request<B>,
+    encoding: Encoding,
+    config: &Config,
+    replay: fn(Bytes) -> B,
+) -> Result<Request<B>, Error>
+where
+    B: HttpBody,
+    B::Error: Into<BoxErr>,
+{
+    let size = req.body().size_hint();
+    if req.headers().contains_key(header::CONTENT_ENCODING)
+        || size.lower() < MIN_BYTES as u64
+        || size
+            .upper()
+            .is_none_or(|len| len > config.replay_body_limit as u64)
+    {
+        return Ok(req);
+    }
+    let (mut parts, body) = req.into_parts();
+    let budget = config.in_flight_bytes.as_ref();
+    let body = collect(body, config.replay_body_limit, budget).await?;
+    let encoded = encoding.encode(&body);
+    if encoded.len() >= body.len() {
+        set_content_length(&mut parts.headers, body.len());
+        return Ok(Request::from_parts(parts, replay(body)));
+    }
+
+    set_content_length(&mut parts.headers, encoded.len());
+    parts
+        .headers
+        .insert(header::CONTENT_ENCODING, encoding.header_value());
+    Ok(Request::from_parts(parts, replay(encoded.into())))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_helper;
+
+    use flate2::read::{GzDecoder, ZlibDecoder};
+
+    use http::{Response, StatusCode};
+    use hyper::body::Body;
+
+    use std::io::Read;
+
+    #[test]
+    fn encode() {
+        let body = "compressible ".repeat(100);
+        let mut decoded = String::new();
+        let gzip = Encoding::Gzip.encode(body.as_bytes());
+        assert!(GzDecoder::new(&gzip[..])
+            .read_to_string(&mut decoded)
+            .is_ok());
+        assert_eq!(decoded, body);
+
+        decoded.clear();
+        let deflate = Encoding::Deflate.encode(body.as_bytes());
+        assert!(ZlibDecoder::new(&deflate[..])
+            .read_to_string(&mut decoded)
+            .is_ok());
+        assert_eq!(decoded, body);
+    }
+
+    #[tokio::test]
+    async fn stream_encoder() {
+        let (mut tx, body) = Body::channel();
+        tokio::spawn(async move {
+            for _ in 0..100 {
+                tx.send_data("compressible ".into()).await.unwrap();
+            }
+        });
+        let req = Request::new(body);
+        let req = Encoding::Gzip.transform_request(req);
+        assert_eq!(req.headers()[header::CONTENT_ENCODING], "gzip");
+        let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+        let mut decoded = String::new();
+        assert!(GzDecoder::new(&body[..])
+            .read_to_string(&mut decoded)
+            .is_ok());
+        assert_eq!(decoded, "compressible ".repeat(100));
+
+        let req = Encoding::Gzip.transform_request(Request::new(Body::empty()));
+        assert!(!req.headers().contains_key(header::CONTENT_ENCODING));
+    }
+
+    #[tokio::test]
+    async fn compress_request() {
+        // Echoes the decompressed body, with the `Content-Encoding` of the request, unless
+        // `Content-Length` is not of the (compressed) body.
+        let addr = test_helper::serve(|req: Request<Body>| async move {
+            let encoding = req.headers().get(header::CONTENT_ENCODING).cloned();
+            let length = req.headers().get(header::CONTENT_LENGTH).cloned();
+            let body = hyper::body::to_bytes(req.into_body()).await?;
+            let decoded = match &encoding {
+                _ if length.is_some_and(|len| len != HeaderValue::from(body.len())) => {
+                    Err(std::io::ErrorKind::InvalidData.into())
+                }
+                Some(encoding) if encoding == "gzip" => {
+                    let mut decoded = Vec::new();
+                    GzDecoder::new(&body[..])
+                        .read_to_end(&mut decoded)
+                        .map(|_| decoded)
+                }
+                _ => Ok(body.to_vec()),
+            };
+            let mut res = match decoded {
+                Ok(decoded) => Response::new(Body::from(decoded)),
+                Err(_) => {
+                    let mut res = Response::new(Body::empty());
+                    *res.status_mut() = StatusCode::BAD_REQUEST;
+                    res
+                }
+            };
+            if let Some(encoding) = encoding {
+                res.headers_mut().insert("x-encoding", encoding);
+            }
+            Ok(res)
+        });
+        let builder = crate::builder_http(addr.to_string());
+        assert!(builder.is_ok());
+        let mut svc = builder
+            .unwrap()
+            .compress_request(Encoding::Gzip)
+            .build(crate::Identity);
+
+        let large = "compressible ".repeat(200);
+        for (body, encoded) in [(large.as_str(), true), ("small", false)] {
+            let req = Request::builder()
+                .method("POST")
+                .uri("https://test.com/upload")
+                .body(Body::from(body.to_owned()))
+                .unwrap();
+            let res = tower_service::Service::call(&mut svc, req).await.unwrap();
+            assert!(res.is_ok());
+            let res = res.unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+            assert_eq!(res.headers().contains_key("x-encoding"), encoded);
+            let echoed = hyper::body::to_bytes(res.into_body()).await;
+            assert_eq!(echoed.unwrap(), body);
+        }
+
+        // Neither a body above the replay limit nor one of unknown size is compressed.
+        let builder = crate::builder_http(addr.to_string());
+        let mut svc = builder
+            .unwrap()
+            .compress_request(Encoding::Gzip)
+            .replay_body_limit(2 * MIN_BYTES)
+            .build(crate::Identity);
+        let (mut tx, chunked) = Body::channel();
+        let sent = large.clone();
+        tokio::spawn(async move { tx.send_data(sent.into()).await });
+        for body in [Body::from(large.clone()), chunked] {
+            let req = Request::builder()
+                .method("POST")
+                .uri("https://test.com/upload")
+                .body(body)
+                .unwrap();
+            let res = tower_service::Service::call(&mut svc, req).await.unwrap();
+            assert!(res.is_ok());
+            let res = res.unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+            assert!(!res.headers().contains_key("x-encoding"));
+            let echoed = hyper::body::to_bytes(res.into_body()).await;
+            assert_eq!(echoed.unwrap(), large);
+        }
+    }
+}
@@ -0,0 +1,484 @@
+use http::header::{self, HeaderMap, HeaderName};
+use http::uri::{Authority, PathAndQuery, Scheme, Uri};
+use http::Error as HttpError;
+use http::{Request, Response, StatusCode, Version};
+
+use hyper::body::Body;
+use hyper::client::ResponseFuture;
+
+use crate::access_log::AccessLogger;
+use crate::auth::Authorization;
+use crate::balance::Balancer;
+use crate::breaker::Breaker;
+use crate::cache::Cache;
+use crate::cookie::RewriteSetCookie;
+use crate::drain::Drain;
+use crate::exec::Exec;
+use crate::forwarded::ClientIpHeader;
+use crate::pool::PoolStats;
+use crate::request_id::RequestId;
+use crate::retry::Retry;
+use crate::singleflight::Singleflight;
+use crate::stats::Stats;
+use crate::upgrade::UpgradeFn;
+use crate::{BodyTransform, Error};
+
+use std::any::Any;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::sync::Semaphore;
+
+pub(crate) type MapStatus = Arc<dyn Fn(StatusCode) -> StatusCode + Send + Sync>;
+/// Sends a request by a client with any connector, see [`Config::scheme_client()`].
+pub(crate) type SchemeClient<B> = Arc<dyn Fn(Request<B>) -> ResponseFuture + Send + Sync>;
+pub(crate) type MapUri = Arc<dyn Fn(&Uri) -> Result<Uri, HttpError> + Send + Sync>;
+pub(crate) type OnResponse = Arc<
+    dyn Fn(Response<Body>) -> Pin<Box<dyn Future<Output = Response<Body>> + Send>> + Send + Sync,
+>;
+
+/// Options shared by the services built from the same [`Builder`](crate::ReusedServiceBuilder).
+#[derive(Clone)]
+pub(crate) struct Config {
+    pub(crate) default_headers: HeaderMap,
+    pub(crate) remove_request_headers: Vec<HeaderName>,
+    pub(crate) client_ip_header: Option<ClientIpHeader>,
+    pub(crate) scheme_from_forwarded: bool,
+    pub(crate) response_header_allowlist: Option<Vec<HeaderName>>,
+    pub(crate) remove_response_headers: Vec<HeaderName>,
+    pub(crate) strip_cors_headers: bool,
+    pub(crate) cors_headers: HeaderMap,
+    pub(crate) map_status: Option<MapStatus>,
+    pub(crate) on_response: Option<OnResponse>,
+    pub(crate) map_uri: Option<MapUri>,
+    pub(crate) forward_proxy: bool,
+    pub(crate) balancer: Option<Arc<Balancer>>,
+    /// [`SchemeClient`]s, erased since `Config` is not generic over the body type.
+    pub(crate) scheme_clients: Vec<(Scheme, Arc<dyn Any + Send + Sync>)>,
+    pub(crate) request_id: Option<Arc<RequestId>>,
+    pub(crate) base_path: Option<String>,
+    pub(crate) raw_path: bool,
+    pub(crate) max_redirects: Option<usize>,
+    pub(crate) cross_host_redirects: bool,
+    pub(crate) retry: Retry,
+    pub(crate) replay_body_limit: usize,
+    pub(crate) rewrite_set_cookie: Option<RewriteSetCookie>,
+    pub(crate) http_version: Option<Version>,
+    pub(crate) concurrency: Option<Arc<Semaphore>>,
+    pub(crate) in_flight_bytes: Option<Arc<Semaphore>>,
+    pub(crate) breaker: Option<Arc<Breaker>>,
+    pub(crate) buffer_response: Option<usize>,
+    pub(crate) access_log: Option<Arc<dyn AccessLogger>>,
+    pub(crate) authorization: Option<Authorization>,
+    pub(crate) on_upgrade: Option<UpgradeFn>,
+    pub(crate) cache: Option<Arc<Cache>>,
+    pub(crate) cache_vary: Vec<HeaderName>,
+    pub(crate) singleflight: Option<Arc<Singleflight>>,
+    pub(crate) mirror: Option<Authority>,
+    pub(crate) preserve_original_uri: bool,
+    pub(crate) executor: Exec,
+    pub(crate) pool_stats: Option<PoolStats>,
+    pub(crate) stats: Stats,
+    pub(crate) drain: Drain,
+    pub(crate) last_uri: Option<Arc<Mutex<Option<Uri>>>>,
+    pub(crate) max_header_bytes: Option<usize>,
+    pub(crate) sanitize_request_headers: bool,
+    pub(crate) sanitize_uri: bool,
+    pub(crate) head_as_get: bool,
+    pub(crate) count_request_body: bool,
+    pub(crate) max_response_headers: Option<usize>,
+    pub(crate) rewrite_location_relative: bool,
+    pub(crate) public_base: Option<Uri>,
+    pub(crate) rewrite_origin: bool,
+    pub(crate) request_trailers: Option<HeaderMap>,
+    pub(crate) body_transform: Option<Arc<dyn BodyTransform>>,
+    pub(crate) body_timeout: Option<Duration>,
+    #[cfg(feature = "compression")]
+    pub(crate) compress_request: Option<crate::compress::Encoding>,
+    #[cfg(feature = "http3")]
+    pub(crate) http3: Option<crate::http3::Http3Client>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            default_headers: HeaderMap::new(),
+            remove_request_headers: Vec::new(),
+            client_ip_header: None,
+            scheme_from_forwarded: false,
+            response_header_allowlist: None,
+            remove_response_headers: Vec::new(),
+            strip_cors_headers: false,
+            cors_headers: HeaderMap::new(),
+            map_status: None,
+            on_response: None,
+            map_uri: None,
+            forward_proxy: false,
+            balancer: None,
+            scheme_clients: Vec::new(),
+            request_id: None,
+            base_path: None,
+            raw_path: false,
+            max_redirects: None,
+            cross_host_redirects: false,
+            retry: Retry::default(),
+            replay_body_limit: 64 * 1024,
+            rewrite_set_cookie: None,
+            http_version: None,
+            concurrency: None,
+            in_flight_bytes: None,
+            breaker: None,
+            buffer_response: None,
+            access_log: None,
+            authorization: None,
+            on_upgrade: None,
+            cache: None,
+            cache_vary: Vec::new(),
+            singleflight: None,
+            mirror: None,
+            preserve_original_uri: false,
+            executor: Exec::default(),
+            pool_stats: None,
+            stats: Stats::default(),
+            drain: Drain::default(),
+            last_uri: None,
+            max_header_bytes: None,
+            sanitize_request_headers: false,
+            sanitize_uri: false,
+            head_as_get: false,
+            count_request_body: false,
+            max_response_headers: None,
+            rewrite_location_relative: false,
+            public_base: None,
+            rewrite_origin: false,
+            request_trailers: None,
+            body_transform: None,
+            body_timeout: None,
+            #[cfg(feature = "compression")]
+            compress_request: None,
+            #[cfg(feature = "http3")]
+            http3: None,
+        }
+    }
+}
+
+impl fmt::Debug for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Config")
+            .field("default_headers", &header_names(&self.default_headers))
+            .field("remove_request_headers", &self.remove_request_headers)
+            .field("client_ip_header", &self.client_ip_header)
+            .field("scheme_from_forwarded", &self.scheme_from_forwarded)
+            .field("response_header_allowlist", &self.response_header_allowlist)
+            .field("remove_response_headers", &self.remove_response_headers)
+            .field("strip_cors_headers", &self.strip_cors_headers)
+            .field("cors_headers", &header_names(&self.cors_headers))
+            .field("forward_proxy", &self.forward_proxy)
+            .field("balancer", &self.balancer)
+            .field(
+                "scheme_clients",
+                &self
+                    .scheme_clients
+                    .iter()
+                    .map(|(s, _)| s)
+                    .collect::<Vec<_>>(),
+            )
+            .field("request_id", &self.request_id)
+            .field("base_path", &self.base_path)
+            .field("raw_path", &self.raw_path)
+            .field("max_redirects", &self.max_redirects)
+            .field("cross_host_redirects", &self.cross_host_redirects)
+            .field("retry", &self.retry)
+            .field("replay_body_limit", &self.replay_body_limit)
+            .field("rewrite_set_cookie", &self.rewrite_set_cookie)
+            .field("http_version", &self.http_version)
+            .field("concurrency", &self.concurrency)
+            .field("in_flight_bytes", &self.in_flight_bytes)
+            .field("breaker", &self.breaker)
+            .field("buffer_response", &self.buffer_response)
+            .field("authorization", &self.authorization)
+            .field("cache", &self.cache)
+            .field("cache_vary", &self.cache_vary)
+            .field("singleflight", &self.singleflight)
+            .field("mirror", &self.mirror)
+            .field("preserve_original_uri", &self.preserve_original_uri)
+            .field("executor", &self.executor)
+            .field("pool_stats", &self.pool_stats)
+            .field("stats", &self.stats)
+            .field("drain", &self.drain)
+            .field("last_uri", &self.last_uri)
+            .field("max_header_bytes", &self.max_header_bytes)
+            .field("sanitize_request_headers", &self.sanitize_request_headers)
+            .field("sanitize_uri", &self.sanitize_uri)
+            .field("head_as_get", &self.head_as_get)
+            .field("count_request_body", &self.count_request_body)
+            .field("max_response_headers", &self.max_response_headers)
+            .field("rewrite_location_relative", &self.rewrite_location_relative)
+            .field("public_base", &self.public_base)
+            .field("rewrite_origin", &self.rewrite_origin)
+            .field(
+                "request_trailers",
+                &self.request_trailers.as_ref().map(header_names),
+            )
+            .field("body_timeout", &self.body_timeout)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The names of `headers`, without the values, which may be secrets such as credentials.
+fn header_names(headers: &HeaderMap) -> Vec<&HeaderName> {
+    headers.keys().collect()
+}
+
+impl Config {
+    /// The client set for `scheme` by
+    /// [`scheme_client()`](crate::ReusedServiceBuilder::scheme_client), if any.
+    pub(crate) fn scheme_client<B: 'static>(&self, scheme: &Scheme) -> Option<SchemeClient<B>> {
+        self.scheme_clients
+            .iter()
+            .find(|(s, _)| s == scheme)
+            .and_then(|(_, client)| client.downcast_ref::<SchemeClient<B>>())
+            .cloned()
+    }
+
+    /// Splits an absolute `uri` into the scheme and the authority, and a config with the path of
+    /// `uri` as the base path. The query is ignored.
+    pub(crate) fn from_uri(
+        uri: Uri,
+        schemes: &[Scheme],
+    ) -> Result<(Scheme, Authority, Self), Error> {
+        let missing = format!("`{uri}` has no authority");
+        let parts = uri.into_parts();
+        let scheme = match parts.scheme {
+            Some(scheme) if schemes.contains(&scheme) => scheme,
+            scheme => return Err(Error::UnsupportedScheme(scheme)),
+        };
+        let authority = match parts.authority {
+            Some(authority) => upstream_authority(authority.as_str())?,
+            None => return Err(Error::InvalidAuthority(missing)),
+        };
+        let base_path = parts
+            .path_and_query
+            .map(|p_and_q| p_and_q.path().to_owned())
+            .filter(|path| !path.trim_matches('/').is_empty());
+        let config = Self {
+            base_path,
+            ..Default::default()
+        };
+        Ok((scheme, authority, config))
+    }
+
+    /// The scheme and authority of an absolute-form request, in the forward-proxy mode.
+    pub(crate) fn forward_target<B>(&self, req: &Request<B>) -> Option<(Scheme, Authority)> {
+        if !self.forward_proxy {
+            return None;
+        }
+        let uri = req.uri();
+        let authority = uri.authority()?.clone();
+        let scheme = uri.scheme().cloned().unwrap_or(Scheme::HTTP);
+        Some((scheme, authority))
+    }
+
+    /// Prepends the base path to the (rewritten) path of the request.
+    pub(crate) fn apply_base_path<B>(&self, req: &mut Request<B>) -> Result<(), HttpError> {
+        let base = match &self.base_path {
+            Some(base) => base,
+            None => return Ok(()),
+        };
+        let uri = req.uri();
+        let mut p_and_q = if self.raw_path {
+            concat_path(base, uri.path())
+        } else {
+            join_path(base, uri.path())
+        };
+        if let Some(query) = uri.query() {
+            p_and_q.push('?');
+            p_and_q.push_str(query);
+        }
+        let mut parts = std::mem::take(req.uri_mut()).into_parts();
+        parts.path_and_query = Some(PathAndQuery::try_from(p_and_q)?);
+        *req.uri_mut() = Uri::from_parts(parts)?;
+        Ok(())
+    }
+
+    /// Runs `f`, which consumes a response body, within the body timeout if any.
+    pub(crate) async fn within_body_timeout<T, F>(&self, f: F) -> Result<T, Error>
+    where
+        F: std::future::Future<Output = Result<T, Error>>,
+    {
+        match self.body_timeout {
+            Some(timeout) => tokio::time::timeout(timeout, f)
+                .await
+                .unwrap_or(Err(Error::Timeout(timeout))),
+            None => f.await,
+        }
+    }
+
+    pub(crate) fn remove_request_headers(&self, headers: &mut HeaderMap) {
+        for name in &self.remove_request_headers {
+            headers.remove(name);
+        }
+    }
+
+    pub(crate) fn apply_default_headers(&self, headers: &mut HeaderMap) {
+        for name in self.default_headers.keys() {
+            headers.remove(name);
+            for value in self.default_headers.get_all(name) {
+                headers.append(name.clone(), value.clone());
+            }
+        }
+        if let Some(value) = self.authorization.as_ref().and_then(Authorization::value) {
+            headers.insert(header::AUTHORIZATION, value);
+        }
+    }
+
+    pub(crate) fn process_response(&self, res: &mut Response<Body>) {
+        if let Some(allowed) = &self.response_header_allowlist {
+            let dropped = res
+                .headers()
+                .keys()
+                .filter(|&name| {
+                    name != header::CONTENT_LENGTH
+                        && name != header::TRANSFER_ENCODING
+                        && !allowed.contains(name)
+                })
+                .cloned()
+                .collect::<Vec<_>>();
+            for name in dropped {
+                res.headers_mut().remove(name);
+            }
+        }
+        for name in &self.remove_response_headers {
+            res.headers_mut().remove(name);
+        }
+        if self.strip_cors_headers {
+            let cors = res
+                .headers()
+                .keys()
+                .filter(|name| name.as_str().starts_with("access-control-"))
+                .cloned()
+                .collect::<Vec<_>>();
+            for name in cors {
+                res.headers_mut().remove(name);
+            }
+        }
+        for name in self.cors_headers.keys() {
+            res.headers_mut().remove(name);
+            for value in self.cors_headers.get_all(name) {
+                res.headers_mut().append(name.clone(), value.clone());
+            }
+        }
+        if let Some(rw) = &self.rewrite_set_cookie {
+            rw.rewrite_headers(res.headers_mut());
+        }
+        if let Some(map_status) = &self.map_status {
+            *res.status_mut() = map_status(res.status());
+        }
+    }
+}
+
+/// Converts `authority` into the authority of an upstream, rejecting the ones `Authority` accepts
+/// but no upstream has: an empty host, *e.g.* `:80`, an empty port, *e.g.* `example.com:`, and
+/// userinfo, *e.g.* `user@example.com`.
+pub(crate) fn upstream_authority<A>(authority: A) -> Result<Authority, Error>
+where
+    Authority: TryFrom<A>,
+    <Authority as TryFrom<A>>::Error: Into<HttpError>,
+{
+    let authority = Authority::try_from(authority).map_err(|e| Error::InvalidUri(e.into()))?;
+    let invalid = |part| Err(Error::InvalidAuthority(format!("`{authority}` {part}")));
+    if authority.as_str().contains('@') {
+        return invalid("has userinfo, which is not allowed for an upstream");
+    }
+    if authority.host().is_empty() {
+        return invalid("has no host");
+    }
+    if authority.as_str().ends_with(':') {
+        return invalid("has an empty port");
+    }
+    Ok(authority)
+}
+
+/// Joins `base` and `path` with exactly one slash.
+pub(crate) fn join_path(base: &str, path: &str) -> String {
+    let base = base.trim_matches('/');
+    let path = path.trim_start_matches('/');
+    let mut joined = String::with_capacity(base.len() + path.len() + 2);
+    joined.push('/');
+    if !base.is_empty() {
+        joined.push_str(base);
+        joined.push('/');
+    }
+    joined.push_str(path);
+    joined
+}
+
+/// Appends `path` to `base`, only adding or removing the slash at the end of `base`, so that
+/// `path` is kept byte for byte.
+fn concat_path(base: &str, path: &str) -> String {
+    let base = base.trim_end_matches('/');
+    let mut joined = String::with_capacity(base.len() + path.len() + 2);
+    if !base.is_empty() && !base.starts_with('/') {
+        joined.push('/');
+    }
+    joined.push_str(base);
+    if !path.starts_with('/') {
+        joined.push('/');
+    }
+    joined.push_str(path);
+    joined
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn upstream_authority() {
+        assert!(super::upstream_authority("example.com").is_ok());
+        assert!(super::upstream_authority("example.com:8080").is_ok());
+        assert!(super::upstream_authority("[::1]:8080").is_ok());
+        for invalid in ["", " ", "example.com/path", "http://example.com"] {
+            let res = super::upstream_authority(invalid);
+            assert!(matches!(res, Err(Error::InvalidUri(_))), "{invalid:?}");
+        }
+        for (invalid, msg) in [
+            (":80", "`:80` has no host"),
+            ("example.com:", "`example.com:` has an empty port"),
+            ("user@example.com", "`user@example.com` has userinfo"),
+            (
+                "user:pass@example.com:80",
+                "`user:pass@example.com:80` has userinfo",
+            ),
+        ] {
+            let res = super::upstream_authority(invalid);
+            assert!(
+                matches!(&res, Err(Error::InvalidAuthority(e)) if e.starts_with(msg)),
+                "{invalid:?}: {res:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn join() {
+        assert_eq!(join_path("/base", "/foo"), "/base/foo");
+        assert_eq!(join_path("/base/", "/foo"), "/base/foo");
+        assert_eq!(join_path("/base", "foo"), "/base/foo");
+        assert_eq!(join_path("base//", "//foo"), "/base/foo");
+        assert_eq!(join_path("/base", "/"), "/base/");
+        assert_eq!(join_path("/", "/foo"), "/foo");
+        assert_eq!(join_path("", "foo"), "/foo");
+    }
+
+    #[test]
+    fn concat_path() {
+        assert_eq!(super::concat_path("/base", "/foo"), "/base/foo");
+        assert_eq!(super::concat_path("base/", "//foo//"), "/base//foo//");
+        assert_eq!(super::concat_path("/base", "foo"), "/base/foo");
+        assert_eq!(super::concat_path("/", "/a%2Fb"), "/a%2Fb");
+        assert_eq!(super::concat_path("", "foo"), "/foo");
+    }
+}
@@ -0,0 +1,100 @@
+use http::header::{Entry, HeaderMap, HeaderValue, SET_COOKIE};
+
+/// Rewrites the `Domain` and `Path` attributes of `Set-Cookie` headers of responses to the
+/// public-facing values.
+///
+/// For each attribute, `None` leaves it as is, `Some("")` removes it, and `Some(value)` replaces
+/// it with `value`. An attribute absent in a cookie is not added. The attribute names are matched
+/// case-insensitively.
+///
+/// ```
+/// # use reverse_proxy_service::RewriteSetCookie;
+/// let rw = RewriteSetCookie {
+///     domain: Some("example.com".into()),
+///     path: Some("/app".into()),
+/// };
+/// assert_eq!(
+///     rw.rewrite("id=1; path=/; HttpOnly; DOMAIN=internal.local"),
+///     "id=1; Path=/app; HttpOnly; Domain=example.com"
+/// );
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RewriteSetCookie {
+    pub domain: Option<String>,
+    pub path: Option<String>,
+}
+
+impl RewriteSetCookie {
+    /// Rewrites a value of a `Set-Cookie` header.
+    pub fn rewrite(&self, cookie: &str) -> String {
+        let mut attrs = cookie.split(';');
+        let mut ret = attrs.next().unwrap_or_default().trim().to_owned();
+        for attr in attrs {
+            let attr = attr.trim();
+            let name = attr.split('=').next().unwrap_or_default().trim();
+            let replaced = if name.eq_ignore_ascii_case("domain") {
+                self.domain.as_deref().map(|v| ("Domain", v))
+            } else if name.eq_ignore_ascii_case("path") {
+                self.path.as_deref().map(|v| ("Path", v))
+            } else {
+                None
+            };
+            match replaced {
+                Some((_, "")) => {}
+                Some((name, value)) => {
+                    ret.push_str("; ");
+                    ret.push_str(name);
+                    ret.push('=');
+                    ret.push_str(value);
+                }
+                None => {
+                    ret.push_str("; ");
+                    ret.push_str(attr);
+                }
+            }
+        }
+        ret
+    }
+
+    pub(crate) fn rewrite_headers(&self, headers: &mut HeaderMap) {
+        let cookies: Vec<_> = match headers.entry(SET_COOKIE) {
+            Entry::Occupied(entry) => entry.remove_entry_mult().1.collect(),
+            Entry::Vacant(_) => return,
+        };
+        for cookie in cookies {
+            let rewritten = cookie
+                .to_str()
+                .ok()
+                .and_then(|c| HeaderValue::try_from(self.rewrite(c)).ok());
+            headers.append(SET_COOKIE, rewritten.unwrap_or(cookie));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rewrite() {
+        let rw = RewriteSetCookie {
+            domain: Some("example.com".into()),
+            path: Some("/app".into()),
+        };
+        assert_eq!(
+            rw.rewrite("a=b; Domain=internal; Path=/x; Secure"),
+            "a=b; Domain=example.com; Path=/app; Secure"
+        );
+        assert_eq!(
+            rw.rewrite("a=b;secure;path = /x ;domain=internal"),
+            "a=b; secure; Path=/app; Domain=example.com"
+        );
+        assert_eq!(rw.rewrite("a=b; Max-Age=10"), "a=b; Max-Age=10");
+
+        let rw = RewriteSetCookie {
+            domain: Some("".into()),
+            path: None,
+        };
+        assert_eq!(rw.rewrite("a=b; Domain=internal; Path=/x"), "a=b; Path=/x");
+    }
+}
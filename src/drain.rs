@@ -0,0 +1,91 @@
+use tokio::sync::Notify;
+
+use std::future::Future;
+use std::pin::pin;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// The requests in flight, shared by the services built by the same builder, and their clones.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Drain(Arc<State>);
+
+#[derive(Debug, Default)]
+struct State {
+    in_flight: AtomicUsize,
+    draining: AtomicBool,
+    idle: Notify,
+}
+
+/// Counts a request in flight until dropped.
+#[derive(Debug)]
+pub(crate) struct InFlight(Drain);
+
+impl Drain {
+    /// Counts a new request in flight, or returns `None` if draining.
+    pub(crate) fn enter(&self) -> Option<InFlight> {
+        let state = &self.0;
+        // Counted before the check, so that `wait()` sees either the request or the flag.
+        state.in_flight.fetch_add(1, Ordering::SeqCst);
+        let in_flight = InFlight(self.clone());
+        (!state.draining.load(Ordering::SeqCst)).then_some(in_flight)
+    }
+
+    pub(crate) fn in_flight(&self) -> usize {
+        self.0.in_flight.load(Ordering::SeqCst)
+    }
+
+    /// Rejects new requests, and returns a future resolving when no request is in flight.
+    pub(crate) fn wait(&self) -> impl Future<Output = ()> + Send + 'static {
+        let state = self.0.clone();
+        state.draining.store(true, Ordering::SeqCst);
+        async move {
+            loop {
+                let mut idle = pin!(state.idle.notified());
+                idle.as_mut().enable();
+                if state.in_flight.load(Ordering::SeqCst) == 0 {
+                    return;
+                }
+                idle.await;
+            }
+        }
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        let state = &(self.0).0;
+        if state.in_flight.fetch_sub(1, Ordering::SeqCst) == 1 {
+            state.idle.notify_waiters();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn wait() {
+        let drain = Drain::default();
+        let first = drain.enter().unwrap();
+        let second = drain.enter().unwrap();
+        assert_eq!(drain.in_flight(), 2);
+
+        let mut idle = tokio::spawn(drain.wait());
+        assert!(drain.enter().is_none());
+        assert_eq!(drain.in_flight(), 2);
+
+        drop(first);
+        let pending = tokio::time::timeout(Duration::from_millis(50), &mut idle).await;
+        assert!(pending.is_err());
+        drop(second);
+        let idle = tokio::time::timeout(Duration::from_secs(5), idle).await;
+        assert!(idle.is_ok());
+        assert_eq!(drain.in_flight(), 0);
+
+        // Already idle.
+        drain.wait().await;
+    }
+}
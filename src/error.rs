@@ -1,4 +1,7 @@
+use http::header::HeaderName;
+use http::uri::{Scheme, Uri};
 use http::Error as HttpError;
+use http::Method;
 use hyper::Error as HyperError;
 
 #[cfg(feature = "axum")]
@@ -8,11 +11,83 @@ use http::StatusCode;
 
 use std::error::Error as StdError;
 use std::fmt;
+use std::time::Duration;
 
+type BoxErr = Box<dyn StdError + Send + Sync>;
+
+/// The error of the services.
+///
+/// New variants may be added in the future, so that a `match` needs a wildcard arm. To branch on
+/// the category, use [`kind()`](Self::kind) or the predicates such as
+/// [`is_connect()`](Self::is_connect).
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Error {
     InvalidUri(HttpError),
+    /// The authority has no host or an empty port, or has userinfo, which is not sent to an
+    /// upstream. The message tells which part is wrong.
+    InvalidAuthority(String),
     RequestFailed(HyperError),
+    /// Failed to resolve the name of the upstream.
+    Dns(HyperError),
+    /// Failed to establish a TCP connection to the upstream, *e.g.* connection refused.
+    Connect(HyperError),
+    /// Failed in the TLS handshake with the upstream.
+    Tls(HyperError),
+    /// The request body failed in the middle of the stream, either while buffered or while sent
+    /// to the upstream with
+    /// [`Builder::count_request_body()`](crate::ReusedServiceBuilder::count_request_body), or the
+    /// response body did while buffered, *e.g.* by
+    /// [`Builder::buffer_response()`](crate::ReusedServiceBuilder::buffer_response).
+    BodyStream {
+        /// The bytes read before the failure.
+        transferred: usize,
+        source: BoxErr,
+    },
+    /// The upstream redirected more than the limit.
+    TooManyRedirects(usize),
+    /// The upstream redirected to the URI already visited.
+    RedirectLoop(Uri),
+    /// Too many requests or buffered bytes are in flight, see
+    /// [`Builder::max_concurrency()`](crate::ReusedServiceBuilder::max_concurrency) and
+    /// [`Builder::max_in_flight_bytes()`](crate::ReusedServiceBuilder::max_in_flight_bytes).
+    Overloaded,
+    /// The response body exceeded the limit, see
+    /// [`Builder::buffer_response()`](crate::ReusedServiceBuilder::buffer_response).
+    BodyTooLarge(usize),
+    /// The scheme of the upstream URI is missing or not supported by the client.
+    UnsupportedScheme(Option<Scheme>),
+    /// The upstream responded with `101 Switching Protocols`, but upgrading the connection is not
+    /// supported without
+    /// [`Builder::on_upgrade()`](crate::ReusedServiceBuilder::on_upgrade).
+    UpgradeUnsupported,
+    /// The method cannot be proxied, *i.e.* `CONNECT`, which asks for a tunnel rather than a
+    /// request to the upstream.
+    MethodNotAllowed(Method),
+    /// The request headers exceeded the limit, see
+    /// [`Builder::max_header_bytes()`](crate::ReusedServiceBuilder::max_header_bytes).
+    HeadersTooLarge(usize),
+    /// The response body did not complete in time, see
+    /// [`Builder::body_timeout()`](crate::ReusedServiceBuilder::body_timeout).
+    Timeout(Duration),
+    /// The upstream responded with more headers than the limit, see
+    /// [`Builder::max_response_headers()`](crate::ReusedServiceBuilder::max_response_headers).
+    UpstreamHeaders(usize),
+    /// The upstream failed repeatedly and the circuit is open, see
+    /// [`Builder::circuit_breaker()`](crate::ReusedServiceBuilder::circuit_breaker).
+    CircuitOpen,
+    /// The request has a malformed or conflicting header of the name, see
+    /// [`sanitize_request_headers()`](crate::ReusedServiceBuilder::sanitize_request_headers), or
+    /// the header to send is not a valid value, see
+    /// [`static_bearer_token()`](crate::ReusedServiceBuilder::static_bearer_token).
+    InvalidRequestHeader(HeaderName),
+    /// The path is rejected by [`Deny`](crate::rewrite::Deny), before contacting the upstream.
+    Forbidden(String),
+    /// The service is [draining](crate::ReusedService::drain) and accepts no new request.
+    Draining,
+    /// The request over HTTP/3 failed, either in the QUIC handshake or on the stream, see
+    /// [`builder_http3()`](crate::builder_http3).
+    Http3(BoxErr),
 }
 
 impl fmt::Display for Error {
@@ -21,20 +96,363 @@ impl fmt::Display for Error {
             Self::InvalidUri(e) => {
                 write!(f, "Invalid uri: {e}")
             }
+            Self::InvalidAuthority(msg) => {
+                write!(f, "Invalid authority: {msg}")
+            }
             Self::RequestFailed(e) => {
                 write!(f, "Request failed: {e}")
             }
+            Self::Dns(e) => {
+                write!(f, "DNS resolution failed: {e}")
+            }
+            Self::Connect(e) => {
+                write!(f, "Connection failed: {e}")
+            }
+            Self::Tls(e) => {
+                write!(f, "TLS handshake failed: {e}")
+            }
+            Self::BodyStream {
+                transferred,
+                source,
+            } => {
+                write!(f, "Body stream failed after {transferred} bytes: {source}")
+            }
+            Self::TooManyRedirects(max) => {
+                write!(f, "Too many redirects: exceeded {max}")
+            }
+            Self::RedirectLoop(uri) => {
+                write!(f, "Redirect loop: {uri} is already visited")
+            }
+            Self::Overloaded => {
+                write!(f, "Overloaded: too many requests or bytes in flight")
+            }
+            Self::BodyTooLarge(max) => {
+                write!(f, "Response body too large: exceeded {max} bytes")
+            }
+            Self::UnsupportedScheme(Some(scheme)) => {
+                write!(f, "Unsupported scheme: {scheme}")
+            }
+            Self::UnsupportedScheme(None) => {
+                write!(f, "Unsupported scheme: no scheme")
+            }
+            Self::UpgradeUnsupported => {
+                write!(f, "Upgrade unsupported: the upstream switched protocols")
+            }
+            Self::MethodNotAllowed(method) => {
+                write!(f, "Method not allowed: {method}")
+            }
+            Self::HeadersTooLarge(max) => {
+                write!(f, "Request headers too large: exceeded {max} bytes")
+            }
+            Self::Timeout(timeout) => {
+                write!(
+                    f,
+                    "Timeout: the response body did not complete in {timeout:?}"
+                )
+            }
+            Self::UpstreamHeaders(max) => {
+                write!(f, "Too many response headers: exceeded {max}")
+            }
+            Self::CircuitOpen => {
+                write!(f, "Circuit open: the upstream failed repeatedly")
+            }
+            Self::InvalidRequestHeader(name) => {
+                write!(f, "Invalid request header: {name}")
+            }
+            Self::Forbidden(path) => {
+                write!(f, "Forbidden path: {path}")
+            }
+            Self::Draining => {
+                write!(f, "Draining: the service accepts no new request")
+            }
+            Self::Http3(e) => {
+                write!(f, "HTTP/3 request failed: {e}")
+            }
         }
     }
 }
 
 impl StdError for Error {}
 
+/// The category of an [`Error`], see [`Error::kind()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// [`Error::InvalidUri`]
+    InvalidUri,
+    /// [`Error::InvalidAuthority`]
+    InvalidAuthority,
+    /// [`Error::RequestFailed`]
+    Request,
+    /// [`Error::Dns`]
+    Dns,
+    /// [`Error::Connect`]
+    Connect,
+    /// [`Error::Tls`]
+    Tls,
+    /// [`Error::BodyStream`]
+    BodyStream,
+    /// [`Error::TooManyRedirects`] and [`Error::RedirectLoop`]
+    Redirect,
+    /// [`Error::Overloaded`]
+    Overloaded,
+    /// [`Error::BodyTooLarge`]
+    BodyTooLarge,
+    /// [`Error::UnsupportedScheme`]
+    UnsupportedScheme,
+    /// [`Error::UpgradeUnsupported`]
+    UpgradeUnsupported,
+    /// [`Error::MethodNotAllowed`]
+    MethodNotAllowed,
+    /// [`Error::HeadersTooLarge`]
+    HeadersTooLarge,
+    /// [`Error::Timeout`]
+    Timeout,
+    /// [`Error::UpstreamHeaders`]
+    UpstreamHeaders,
+    /// [`Error::CircuitOpen`]
+    CircuitOpen,
+    /// [`Error::InvalidRequestHeader`]
+    InvalidRequestHeader,
+    /// [`Error::Forbidden`]
+    Forbidden,
+    /// [`Error::Draining`]
+    Draining,
+    /// [`Error::Http3`]
+    Http3,
+}
+
+impl Error {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Self::InvalidUri(_) => ErrorKind::InvalidUri,
+            Self::InvalidAuthority(_) => ErrorKind::InvalidAuthority,
+            Self::RequestFailed(_) => ErrorKind::Request,
+            Self::Dns(_) => ErrorKind::Dns,
+            Self::Connect(_) => ErrorKind::Connect,
+            Self::Tls(_) => ErrorKind::Tls,
+            Self::BodyStream { .. } => ErrorKind::BodyStream,
+            Self::TooManyRedirects(_) | Self::RedirectLoop(_) => ErrorKind::Redirect,
+            Self::Overloaded => ErrorKind::Overloaded,
+            Self::BodyTooLarge(_) => ErrorKind::BodyTooLarge,
+            Self::UnsupportedScheme(_) => ErrorKind::UnsupportedScheme,
+            Self::UpgradeUnsupported => ErrorKind::UpgradeUnsupported,
+            Self::MethodNotAllowed(_) => ErrorKind::MethodNotAllowed,
+            Self::HeadersTooLarge(_) => ErrorKind::HeadersTooLarge,
+            Self::Timeout(_) => ErrorKind::Timeout,
+            Self::UpstreamHeaders(_) => ErrorKind::UpstreamHeaders,
+            Self::CircuitOpen => ErrorKind::CircuitOpen,
+            Self::InvalidRequestHeader(_) => ErrorKind::InvalidRequestHeader,
+            Self::Forbidden(_) => ErrorKind::Forbidden,
+            Self::Draining => ErrorKind::Draining,
+            Self::Http3(_) => ErrorKind::Http3,
+        }
+    }
+
+    /// Whether the upstream could not be reached, that is, [`Dns`](Self::Dns),
+    /// [`Connect`](Self::Connect) or [`Tls`](Self::Tls).
+    pub fn is_connect(&self) -> bool {
+        matches!(self, Self::Dns(_) | Self::Connect(_) | Self::Tls(_))
+    }
+
+    /// Whether the client timed out, see [`hyper::Error::is_timeout()`], or the response body
+    /// did, see [`Timeout`](Self::Timeout).
+    pub fn is_timeout(&self) -> bool {
+        match self {
+            Self::RequestFailed(e) | Self::Dns(e) | Self::Connect(e) | Self::Tls(e) => {
+                e.is_timeout()
+            }
+            Self::Timeout(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Whether following the redirects failed.
+    pub fn is_redirect(&self) -> bool {
+        self.kind() == ErrorKind::Redirect
+    }
+
+    /// Whether a body failed while buffered, or was too large.
+    pub fn is_body(&self) -> bool {
+        matches!(self, Self::BodyStream { .. } | Self::BodyTooLarge(_))
+    }
+
+    pub fn is_overloaded(&self) -> bool {
+        matches!(self, Self::Overloaded)
+    }
+
+    /// Classifies an error of the client into [`Dns`](Self::Dns), [`Connect`](Self::Connect) or
+    /// [`Tls`](Self::Tls) by its source chain, falling back to
+    /// [`RequestFailed`](Self::RequestFailed).
+    ///
+    /// The errors of the TLS crates, and the [`io::Error`](std::io::Error)s of a failed connection
+    /// are recognized by type. A DNS error has no type of its own, so that it is recognized by the
+    /// message of `HttpConnector` as the last resort.
+    pub(crate) fn from_hyper(e: HyperError) -> Self {
+        if !e.is_connect() {
+            return Self::RequestFailed(e);
+        }
+
+        let mut connect = false;
+        let mut source = e.source();
+        while let Some(err) = source {
+            if is_tls(err) {
+                return Self::Tls(e);
+            }
+            let io = err.downcast_ref::<std::io::Error>();
+            connect |= io.is_some_and(|io| is_connect(io.kind()));
+            // `io::Error::source()` skips the error it wraps.
+            source = match io {
+                Some(io) => io.get_ref().map(|inner| inner as _),
+                None => err.source(),
+            };
+        }
+        if connect {
+            return Self::Connect(e);
+        }
+
+        let msg = e.source().map(ToString::to_string).unwrap_or_default();
+        if msg.starts_with("dns error") {
+            Self::Dns(e)
+        } else if msg.starts_with("tcp ") {
+            Self::Connect(e)
+        } else {
+            Self::RequestFailed(e)
+        }
+    }
+}
+
+/// Whether an I/O error of `kind` means that no connection is established to the address.
+fn is_connect(kind: std::io::ErrorKind) -> bool {
+    use std::io::ErrorKind::*;
+    matches!(
+        kind,
+        ConnectionRefused
+            | ConnectionReset
+            | ConnectionAborted
+            | NotConnected
+            | AddrInUse
+            | AddrNotAvailable
+            | TimedOut
+            | HostUnreachable
+            | NetworkUnreachable
+            | NetworkDown
+    )
+}
+
+#[allow(unused_variables)]
+fn is_tls(err: &(dyn StdError + 'static)) -> bool {
+    #[cfg(feature = "nativetls")]
+    if err.is::<hyper_tls::native_tls::Error>() {
+        return true;
+    }
+    #[cfg(feature = "__rustls")]
+    if let Some(io) = err.downcast_ref::<std::io::Error>() {
+        if io
+            .get_ref()
+            .is_some_and(|inner| inner.is::<rustls::Error>())
+        {
+            return true;
+        }
+    }
+    false
+}
+
 #[cfg(feature = "axum")]
 #[cfg_attr(docsrs, doc(cfg(feature = "axum")))]
 impl IntoResponse for Error {
     fn into_response(self) -> Response {
         log::error!("{self}");
-        StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        let status = match self {
+            Self::Dns(_)
+            | Self::Connect(_)
+            | Self::Tls(_)
+            | Self::UpgradeUnsupported
+            | Self::UpstreamHeaders(_)
+            | Self::Http3(_) => StatusCode::BAD_GATEWAY,
+            Self::Overloaded | Self::CircuitOpen | Self::Draining => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+            Self::MethodNotAllowed(_) => StatusCode::METHOD_NOT_ALLOWED,
+            Self::InvalidRequestHeader(_) | Self::InvalidAuthority(_) => StatusCode::BAD_REQUEST,
+            Self::Forbidden(_) => StatusCode::FORBIDDEN,
+            Self::HeadersTooLarge(_) => StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE,
+            Self::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        status.into_response()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn kind() {
+        let invalid_uri = Error::InvalidUri(Uri::try_from("in valid").unwrap_err().into());
+        assert_eq!(invalid_uri.kind(), ErrorKind::InvalidUri);
+        assert!(!invalid_uri.is_connect());
+        assert!(!invalid_uri.is_timeout());
+        assert_eq!(
+            Error::InvalidAuthority("`:80` has no host".into()).kind(),
+            ErrorKind::InvalidAuthority
+        );
+
+        let body_stream = Error::BodyStream {
+            transferred: 0,
+            source: "closed".into(),
+        };
+        assert_eq!(body_stream.kind(), ErrorKind::BodyStream);
+        assert!(body_stream.is_body());
+        assert!(Error::BodyTooLarge(1).is_body());
+        assert!(!Error::Overloaded.is_body());
+
+        assert!(Error::TooManyRedirects(1).is_redirect());
+        assert!(Error::RedirectLoop(Uri::from_static("/")).is_redirect());
+        assert_eq!(Error::TooManyRedirects(1).kind(), ErrorKind::Redirect);
+
+        assert!(Error::Overloaded.is_overloaded());
+        assert!(!Error::UpgradeUnsupported.is_overloaded());
+        assert_eq!(
+            Error::UnsupportedScheme(None).kind(),
+            ErrorKind::UnsupportedScheme
+        );
+        assert_eq!(
+            Error::UpgradeUnsupported.kind(),
+            ErrorKind::UpgradeUnsupported
+        );
+        assert_eq!(
+            Error::MethodNotAllowed(Method::CONNECT).kind(),
+            ErrorKind::MethodNotAllowed
+        );
+        assert_eq!(Error::HeadersTooLarge(1).kind(), ErrorKind::HeadersTooLarge);
+        assert_eq!(Error::UpstreamHeaders(1).kind(), ErrorKind::UpstreamHeaders);
+        assert_eq!(Error::CircuitOpen.kind(), ErrorKind::CircuitOpen);
+        assert_eq!(
+            Error::InvalidRequestHeader(http::header::HOST).kind(),
+            ErrorKind::InvalidRequestHeader
+        );
+        assert_eq!(
+            Error::Forbidden("/internal".into()).kind(),
+            ErrorKind::Forbidden
+        );
+        assert_eq!(Error::Draining.kind(), ErrorKind::Draining);
+        assert_eq!(Error::Http3("reset".into()).kind(), ErrorKind::Http3);
+    }
+
+    #[tokio::test]
+    async fn kind_connect() {
+        // Nothing listens on the port 1.
+        let client = hyper::Client::new();
+        let e = client
+            .get(Uri::from_static("http://127.0.0.1:1"))
+            .await
+            .unwrap_err();
+        let e = Error::from_hyper(e);
+        assert_eq!(e.kind(), ErrorKind::Connect);
+        assert!(e.is_connect());
+        assert!(!e.is_timeout());
+        assert!(!e.is_redirect());
     }
 }
@@ -0,0 +1,43 @@
+use hyper::rt::Executor;
+
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+pub(crate) type BoxFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Spawns the background tasks of the services, *i.e.* mirrored requests and upgraded
+/// connections: on the given executor if any, or on Tokio.
+#[derive(Clone, Default)]
+pub(crate) struct Exec(Option<Arc<dyn Executor<BoxFuture> + Send + Sync>>);
+
+impl fmt::Debug for Exec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(_) => f.write_str("Exec(custom)"),
+            None => f.write_str("Exec(tokio)"),
+        }
+    }
+}
+
+impl Exec {
+    pub(crate) fn new<E>(exec: E) -> Self
+    where
+        E: Executor<BoxFuture> + Send + Sync + 'static,
+    {
+        Self(Some(Arc::new(exec)))
+    }
+
+    pub(crate) fn spawn<F>(&self, f: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        match &self.0 {
+            Some(exec) => exec.execute(Box::pin(f)),
+            None => {
+                tokio::spawn(f);
+            }
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use http::uri::Uri;
+
+use hyper::client::connect::dns::{GaiResolver, Name};
+use hyper::client::connect::HttpConnector;
+
+use tokio::net::TcpStream;
+
+use tower_service::Service;
+
+use std::future::Future;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+type BoxErr = Box<dyn std::error::Error + Send + Sync>;
+
+/// The IP family of the connections of [`http_with_family()`](crate::client::http_with_family).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum IpFamily {
+    /// Either family, which is the default of [`HttpConnector`].
+    #[default]
+    Any,
+    /// IPv4 only.
+    V4,
+    /// IPv6 only.
+    V6,
+}
+
+impl IpFamily {
+    fn allows(self, ip: IpAddr) -> bool {
+        match self {
+            Self::Any => true,
+            Self::V4 => ip.is_ipv4(),
+            Self::V6 => ip.is_ipv6(),
+        }
+    }
+
+    fn mismatch(self, host: &str) -> io::Error {
+        let family = match self {
+            Self::V6 => "IPv6",
+            _ => "IPv4",
+        };
+        io::Error::new(
+            io::ErrorKind::AddrNotAvailable,
+            format!("`{host}` has no {family} address"),
+        )
+    }
+}
+
+/// Connector connecting only to the addresses of a family, see
+/// [`http_with_family()`](crate::client::http_with_family).
+///
+/// The addresses resolved from a name are filtered before [`HttpConnector`] tries them, and an IP
+/// literal of the other family fails without connecting.
+#[derive(Debug, Clone)]
+pub struct FamilyConnector {
+    inner: HttpConnector<FamilyResolver>,
+    family: IpFamily,
+}
+
+impl FamilyConnector {
+    pub fn new(family: IpFamily, happy_eyeballs: Option<Duration>) -> Self {
+        let mut inner = HttpConnector::new_with_resolver(FamilyResolver {
+            inner: GaiResolver::new(),
+            family,
+        });
+        inner.set_happy_eyeballs_timeout(happy_eyeballs);
+        Self { inner, family }
+    }
+
+    pub fn family(&self) -> IpFamily {
+        self.family
+    }
+}
+
+impl Service<Uri> for FamilyConnector {
+    type Response = TcpStream;
+    type Error = BoxErr;
+    type Future = Pin<Box<dyn Future<Output = Result<TcpStream, BoxErr>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, dst: Uri) -> Self::Future {
+        // HttpConnector does not pass IP literals to the resolver.
+        let host = dst.host().unwrap_or_default();
+        let literal = host.trim_start_matches('[').trim_end_matches(']');
+        if let Ok(ip) = literal.parse::<IpAddr>() {
+            if !self.family.allows(ip) {
+                let err = self.family.mismatch(host);
+                return Box::pin(async move { Err(err.into()) });
+            }
+        }
+        let connecting = self.inner.call(dst);
+        Box::pin(async move { connecting.await.map_err(Into::into) })
+    }
+}
+
+/// Resolver dropping the addresses of the other family than `family`.
+#[derive(Debug, Clone)]
+struct FamilyResolver {
+    inner: GaiResolver,
+    family: IpFamily,
+}
+
+impl Service<Name> for FamilyResolver {
+    type Response = std::vec::IntoIter<SocketAddr>;
+    type Error = io::Error;
+    type Future = Pin<Box<dyn Future<Output = io::Result<Self::Response>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, name: Name) -> Self::Future {
+        let family = self.family;
+        let resolving = self.inner.call(name.clone());
+        Box::pin(async move {
+            let addrs = resolving
+                .await?
+                .filter(|addr| family.allows(addr.ip()))
+                .collect::<Vec<_>>();
+            if addrs.is_empty() {
+                return Err(family.mismatch(name.as_str()));
+            }
+            Ok(addrs.into_iter())
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn family() {
+        // Listens on IPv4 only.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        for host in ["127.0.0.1", "localhost"] {
+            let uri = Uri::try_from(format!("http://{host}:{port}")).unwrap();
+            for timeout in [Some(Duration::from_millis(100)), None] {
+                for (family, ok) in [
+                    (IpFamily::Any, true),
+                    (IpFamily::V4, true),
+                    (IpFamily::V6, false),
+                ] {
+                    let mut conn = FamilyConnector::new(family, timeout);
+                    let stream = conn.call(uri.clone()).await;
+                    if let Ok(stream) = &stream {
+                        assert!(stream.peer_addr().unwrap().is_ipv4());
+                    }
+                    assert_eq!(stream.is_ok(), ok, "{host} {family:?} {timeout:?}");
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,196 @@
+use http::header::{HeaderMap, HeaderName, HeaderValue};
+use http::{Extensions, Uri};
+
+use std::net::{IpAddr, SocketAddr};
+
+const X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+const X_FORWARDED_PROTO: HeaderName = HeaderName::from_static("x-forwarded-proto");
+
+/// The address of the client, from an extension of the incoming request: either [`SocketAddr`]
+/// or, with the `axum` feature, `ConnectInfo<SocketAddr>`.
+pub(crate) fn client_addr(extensions: &Extensions) -> Option<SocketAddr> {
+    let addr = extensions.get::<SocketAddr>().copied();
+    #[cfg(feature = "axum")]
+    let addr = addr.or_else(|| {
+        extensions
+            .get::<axum::extract::ConnectInfo<SocketAddr>>()
+            .map(|info| info.0)
+    });
+    addr
+}
+
+/// The inbound header trusted for the client IP, see
+/// [`Builder::client_ip_header()`](crate::ReusedServiceBuilder::client_ip_header).
+#[derive(Debug, Clone)]
+pub(crate) struct ClientIpHeader {
+    pub(crate) name: HeaderName,
+    pub(crate) trusted_hops: usize,
+}
+
+impl ClientIpHeader {
+    /// Replaces `X-Forwarded-For` with the trusted part of `name`, followed by `peer`.
+    pub(crate) fn forward(&self, headers: &mut HeaderMap, peer: Option<IpAddr>) {
+        let mut chain = self.trusted(headers).unwrap_or_default();
+        chain.extend(peer);
+        headers.remove(X_FORWARDED_FOR);
+        if chain.is_empty() {
+            return;
+        }
+        let chain = chain
+            .iter()
+            .map(IpAddr::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+        if let Ok(chain) = HeaderValue::try_from(chain) {
+            headers.insert(X_FORWARDED_FOR, chain);
+        }
+    }
+
+    /// The client IP and the proxies after it, *i.e.* the last `trusted_hops` entries of `name`,
+    /// or `None` if there are not as many valid entries.
+    fn trusted(&self, headers: &HeaderMap) -> Option<Vec<IpAddr>> {
+        if self.trusted_hops == 0 {
+            return None;
+        }
+        let entries = headers
+            .get_all(&self.name)
+            .iter()
+            .map(|value| value.to_str().ok())
+            .collect::<Option<Vec<_>>>()?;
+        let entries = entries
+            .iter()
+            .flat_map(|value| value.split(','))
+            .collect::<Vec<_>>();
+        let first = entries.len().checked_sub(self.trusted_hops)?;
+        entries[first..]
+            .iter()
+            .map(|entry| parse_ip(entry))
+            .collect()
+    }
+}
+
+/// Replaces `X-Forwarded-Proto` with the scheme the client used: the one in the inbound header,
+/// set by the trusted proxy in front of this one, or else the one of `uri`, defaulting to `http`.
+pub(crate) fn forward_proto(headers: &mut HeaderMap, uri: &Uri) {
+    let scheme = client_scheme(headers)
+        .or_else(|| uri.scheme_str().and_then(known_scheme))
+        .unwrap_or("http");
+    headers.insert(X_FORWARDED_PROTO, HeaderValue::from_static(scheme));
+}
+
+/// The first entry of `X-Forwarded-Proto`, *i.e.* the one of the client, if it is a known scheme.
+fn client_scheme(headers: &HeaderMap) -> Option<&'static str> {
+    let value = headers.get(X_FORWARDED_PROTO)?.to_str().ok()?;
+    known_scheme(value.split(',').next()?.trim())
+}
+
+fn known_scheme(scheme: &str) -> Option<&'static str> {
+    ["http", "https"]
+        .into_iter()
+        .find(|known| scheme.eq_ignore_ascii_case(known))
+}
+
+/// Parses an entry of `X-Forwarded-For`, which may have a port, *e.g.* `192.0.2.1:8080`.
+fn parse_ip(entry: &str) -> Option<IpAddr> {
+    let entry = entry.trim();
+    entry
+        .parse()
+        .ok()
+        .or_else(|| entry.parse::<SocketAddr>().ok().map(|addr| addr.ip()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn forward(name: &str, trusted_hops: usize, inbound: &[(&str, &str)]) -> Option<String> {
+        let header = ClientIpHeader {
+            name: HeaderName::try_from(name).unwrap(),
+            trusted_hops,
+        };
+        let mut headers = HeaderMap::new();
+        for (name, value) in inbound {
+            headers.append(HeaderName::try_from(*name).unwrap(), value.parse().unwrap());
+        }
+        header.forward(&mut headers, Some(IpAddr::from([10, 0, 0, 1])));
+        let forwarded = headers.get(X_FORWARDED_FOR)?;
+        Some(forwarded.to_str().unwrap().to_owned())
+    }
+
+    #[test]
+    fn x_forwarded_for() {
+        let xff = "x-forwarded-for";
+        assert_eq!(
+            forward(xff, 1, &[(xff, "198.51.100.1, 192.0.2.1")]).as_deref(),
+            Some("192.0.2.1, 10.0.0.1")
+        );
+        assert_eq!(
+            forward(xff, 2, &[(xff, "198.51.100.1"), (xff, "192.0.2.1:8080")]).as_deref(),
+            Some("198.51.100.1, 192.0.2.1, 10.0.0.1")
+        );
+        // Too few hops, or an invalid entry, are not trusted.
+        assert_eq!(
+            forward(xff, 2, &[(xff, "192.0.2.1")]).as_deref(),
+            Some("10.0.0.1")
+        );
+        assert_eq!(
+            forward(xff, 1, &[(xff, "192.0.2.1, unknown")]).as_deref(),
+            Some("10.0.0.1")
+        );
+        assert_eq!(
+            forward(xff, 0, &[(xff, "192.0.2.1")]).as_deref(),
+            Some("10.0.0.1")
+        );
+        assert_eq!(forward(xff, 1, &[]).as_deref(), Some("10.0.0.1"));
+    }
+
+    #[test]
+    fn x_forwarded_proto() {
+        let proto = |inbound: Option<&str>, uri: &str| {
+            let mut headers = HeaderMap::new();
+            if let Some(inbound) = inbound {
+                headers.insert(X_FORWARDED_PROTO, inbound.parse().unwrap());
+            }
+            forward_proto(&mut headers, &uri.parse().unwrap());
+            let forwarded = headers.get_all(X_FORWARDED_PROTO).iter();
+            forwarded
+                .map(|value| value.to_str().unwrap().to_owned())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(proto(Some("https"), "/"), ["https"]);
+        assert_eq!(proto(Some("HTTPS, http"), "http://test.com/"), ["https"]);
+        assert_eq!(proto(None, "/"), ["http"]);
+        assert_eq!(proto(None, "https://test.com/"), ["https"]);
+        // An unknown scheme is not trusted.
+        assert_eq!(proto(Some("gopher"), "/"), ["http"]);
+    }
+
+    #[test]
+    fn x_real_ip() {
+        let real_ip = "x-real-ip";
+        // A spoofed `X-Forwarded-For` is dropped.
+        assert_eq!(
+            forward(
+                real_ip,
+                1,
+                &[(real_ip, "2001:db8::1"), ("x-forwarded-for", "203.0.113.1")]
+            )
+            .as_deref(),
+            Some("2001:db8::1, 10.0.0.1")
+        );
+        assert_eq!(
+            forward(real_ip, 1, &[("x-forwarded-for", "203.0.113.1")]).as_deref(),
+            Some("10.0.0.1")
+        );
+
+        let header = ClientIpHeader {
+            name: HeaderName::from_static("x-real-ip"),
+            trusted_hops: 1,
+        };
+        let mut headers = HeaderMap::new();
+        headers.insert(X_FORWARDED_FOR, HeaderValue::from_static("203.0.113.1"));
+        header.forward(&mut headers, None);
+        assert!(!headers.contains_key(X_FORWARDED_FOR));
+    }
+}
@@ -1,31 +1,110 @@
-use crate::rewrite::PathRewriter;
+use crate::access_log::AccessLog;
+use crate::cache;
+use crate::config::{Config, SchemeClient};
+use crate::forwarded;
+use crate::redirect;
+use crate::retry::Retry;
+use crate::rewrite::{replace_path, AsyncPathRewriter, Denied, OriginalUri, PathRewriter};
+use crate::sanitize::{sanitize, sanitize_uri};
+use crate::trailers;
+use crate::transform::map_body_request;
+use crate::upgrade;
 use crate::Error;
 
+use http::header::{self, HeaderMap, HeaderName, HeaderValue};
+use http::request::Parts;
 use http::uri::{Authority, Scheme};
 use http::Error as HttpError;
-use http::{Request, Response};
+use http::{Method, Request, Response, StatusCode, Uri, Version};
 
-use hyper::body::{Body, HttpBody};
+use hyper::body::{Body, Buf, Bytes, HttpBody};
 use hyper::client::{connect::Connect, Client, ResponseFuture};
+use hyper::upgrade::OnUpgrade;
+
+use tokio::sync::{AcquireError, OwnedSemaphorePermit, Semaphore};
 
 use std::convert::Infallible;
-use std::future::Future;
-use std::pin::Pin;
-use std::task::{Context, Poll};
+use std::fmt;
+use std::future::{self, Future};
+use std::pin::{pin, Pin};
+use std::sync::Arc;
+use std::sync::{Mutex, PoisonError};
+use std::task::{ready, Context, Poll};
+use std::time::Instant;
 
 type BoxErr = Box<dyn std::error::Error + Send + Sync>;
+type BoxFuture = Pin<Box<dyn Future<Output = Result<Response<Body>, Error>> + Send>>;
+
+/// The client sending a request: the one of the service, the one for the scheme of the
+/// upstream, or the HTTP/3 one.
+enum Sender<C, B> {
+    Main(Client<C, B>),
+    Scheme(SchemeClient<B>),
+    #[cfg(feature = "http3")]
+    Http3(crate::http3::Http3Client),
+}
+
+impl<C: Clone, B> Clone for Sender<C, B> {
+    fn clone(&self) -> Self {
+        match self {
+            Self::Main(client) => Self::Main(client.clone()),
+            Self::Scheme(client) => Self::Scheme(client.clone()),
+            #[cfg(feature = "http3")]
+            Self::Http3(client) => Self::Http3(client.clone()),
+        }
+    }
+}
+
+impl<C, B> Sender<C, B>
+where
+    C: Connect + Clone + Send + Sync + 'static,
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<BoxErr>,
+{
+    fn request(&self, req: Request<B>) -> Sending {
+        match self {
+            Self::Main(client) => Sending::Hyper(client.request(req)),
+            Self::Scheme(client) => Sending::Hyper(client(req)),
+            #[cfg(feature = "http3")]
+            Self::Http3(client) => Sending::Http3(client.request(req)),
+        }
+    }
+}
+
+/// The response of [`Sender::request()`], with the errors of hyper classified.
+enum Sending {
+    Hyper(ResponseFuture),
+    #[cfg(feature = "http3")]
+    Http3(BoxFuture),
+}
+
+impl Future for Sending {
+    type Output = Result<Response<Body>, Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.get_mut() {
+            Self::Hyper(fut) => Pin::new(fut).poll(cx).map_err(Error::from_hyper),
+            #[cfg(feature = "http3")]
+            Self::Http3(fut) => fut.as_mut().poll(cx),
+        }
+    }
+}
 
 pub struct RevProxyFuture {
-    inner: Result<ResponseFuture, Option<HttpError>>,
+    inner: BoxFuture,
 }
 
 impl RevProxyFuture {
+    /// `replay` rebuilds a body from buffered bytes, so that a request can be sent more than once.
     pub(crate) fn new<C, B, Pr>(
         client: &Client<C, B>,
-        mut req: Request<B>,
+        req: Request<B>,
         scheme: &Scheme,
         authority: &Authority,
         path: &mut Pr,
+        config: &Arc<Config>,
+        replay: Option<fn(Bytes) -> B>,
     ) -> Self
     where
         C: Connect + Clone + Send + Sync + 'static,
@@ -34,27 +113,856 @@ impl RevProxyFuture {
         B::Error: Into<BoxErr>,
         Pr: PathRewriter,
     {
-        let inner = path
-            .rewrite_uri(&mut req, scheme, authority)
-            .map(|_| client.request(req))
-            .map_err(Some);
+        let rewrite = |mut req: Request<B>, scheme: &Scheme, authority: &Authority| {
+            let rewritten = path.rewrite_uri(&mut req, scheme, authority);
+            future::ready((req, rewritten))
+        };
+        Self::with_rewrite(client, req, scheme, authority, config, replay, rewrite)
+    }
+
+    /// Same as [`new()`](Self::new), but awaits the rewrite before sending the request.
+    pub(crate) fn new_async<C, B, Pr>(
+        client: &Client<C, B>,
+        req: Request<B>,
+        scheme: &Scheme,
+        authority: &Authority,
+        mut path: Pr,
+        config: &Arc<Config>,
+        replay: Option<fn(Bytes) -> B>,
+    ) -> Self
+    where
+        C: Connect + Clone + Send + Sync + 'static,
+        B: HttpBody + Send + 'static,
+        B::Data: Send,
+        B::Error: Into<BoxErr>,
+        Pr: AsyncPathRewriter + Send + 'static,
+    {
+        let rewrite = |mut req: Request<B>, scheme: &Scheme, authority: &Authority| {
+            let (scheme, authority) = (scheme.clone(), authority.clone());
+            async move {
+                let original = req.uri().path().to_owned();
+                let rewritten = {
+                    let path = path.rewrite_async(&original).await;
+                    replace_path(req.uri(), &path, &scheme, &authority)
+                };
+                let rewritten = rewritten.map(|uri| *req.uri_mut() = uri);
+                (req, rewritten)
+            }
+        };
+        Self::with_rewrite(client, req, scheme, authority, config, replay, rewrite)
+    }
+
+    fn with_rewrite<C, B, F, Fut>(
+        client: &Client<C, B>,
+        mut req: Request<B>,
+        scheme: &Scheme,
+        authority: &Authority,
+        config: &Arc<Config>,
+        replay: Option<fn(Bytes) -> B>,
+        rewrite: F,
+    ) -> Self
+    where
+        C: Connect + Clone + Send + Sync + 'static,
+        B: HttpBody + Send + 'static,
+        B::Data: Send,
+        B::Error: Into<BoxErr>,
+        F: FnOnce(Request<B>, &Scheme, &Authority) -> Fut,
+        Fut: Future<Output = (Request<B>, Result<(), HttpError>)> + Send + 'static,
+    {
+        let Some(in_flight) = config.drain.enter() else {
+            return Self::rejected(config, Error::Draining);
+        };
+        // The path of `CONNECT` is an authority, and a tunnel is not a request to the upstream.
+        if req.method() == Method::CONNECT {
+            return Self::rejected(config, Error::MethodNotAllowed(Method::CONNECT));
+        }
+        if let Some(max) = config.max_header_bytes {
+            if header_bytes(req.headers()) > max {
+                return Self::rejected(config, Error::HeadersTooLarge(max));
+            }
+        }
+        if config.sanitize_request_headers {
+            if let Err(err) = sanitize(req.headers_mut()) {
+                return Self::rejected(config, err);
+            }
+        }
+        if config.sanitize_uri {
+            if let Err(err) = sanitize_uri(req.uri()) {
+                return Self::rejected(config, err);
+            }
+        }
+        let admission = match config.breaker.as_ref().map(|breaker| breaker.admit()) {
+            Some(None) => {
+                return Self::rejected(config, Error::CircuitOpen);
+            }
+            Some(admission) => admission,
+            None => None,
+        };
+        let received = Instant::now();
+        // Logged as requested by the client, before `head_as_get` rewrites it.
+        let client_method = config.access_log.as_ref().map(|_| req.method().clone());
+        let head_as_get = config.head_as_get && req.method() == Method::HEAD;
+        if head_as_get {
+            *req.method_mut() = Method::GET;
+        }
+        let public_host = config.rewrite_origin.then(|| public_host(&req)).flatten();
+        let original_uri = config.preserve_original_uri.then(|| req.uri().clone());
+        if let Some(uri) = &original_uri {
+            req.extensions_mut().insert(OriginalUri(uri.clone()));
+        }
+        let target = config.forward_target(&req);
+        let (scheme, authority) = match &target {
+            Some((scheme, authority)) => (scheme, authority),
+            None => match &config.balancer {
+                Some(balancer) => balancer.select(req.uri().path()),
+                None => (scheme, authority),
+            },
+        };
+        if let Some(client_ip) = &config.client_ip_header {
+            let peer = forwarded::client_addr(req.extensions()).map(|addr| addr.ip());
+            client_ip.forward(req.headers_mut(), peer);
+        }
+        if config.scheme_from_forwarded {
+            let uri = req.uri().clone();
+            forwarded::forward_proto(req.headers_mut(), &uri);
+        }
+        config.remove_request_headers(req.headers_mut());
+        config.apply_default_headers(req.headers_mut());
+        if let Some(version) = config.http_version {
+            *req.version_mut() = version;
+        }
+        let request_id = config
+            .request_id
+            .as_ref()
+            .map(|id| id.get_or_insert(req.headers_mut()));
+        let permit = match req.extensions_mut().remove::<Reserved>() {
+            Some(Reserved(permit)) => Some(Ok(permit)),
+            None => config
+                .concurrency
+                .as_ref()
+                .map(|sem| sem.clone().try_acquire_owned()),
+        };
+        let started =
+            client_method.map(|method| (Instant::now(), method, req.uri().path().to_owned()));
+        let on_upgrade = match &config.on_upgrade {
+            Some(_) if req.headers().contains_key(header::UPGRADE) => {
+                Some(hyper::upgrade::on(&mut req))
+            }
+            _ => None,
+        };
+        #[cfg(feature = "proxy-protocol")]
+        let client_addr = forwarded::client_addr(req.extensions());
+        let rewrite = match &config.map_uri {
+            Some(map_uri) => {
+                let mapped = map_uri(req.uri()).map(|uri| *req.uri_mut() = uri);
+                Rewrite::Mapped(Box::new(req), mapped)
+            }
+            None => Rewrite::Path(rewrite(req, scheme, authority)),
+        };
+
+        let client = match config.scheme_client(scheme) {
+            Some(client) => Sender::Scheme(client),
+            None => Sender::Main(client.clone()),
+        };
+        #[cfg(feature = "http3")]
+        let client = match &config.http3 {
+            Some(http3) => Sender::Http3(http3.clone()),
+            None => client,
+        };
+        let config = config.clone();
+        let stats = config.stats.clone();
+        let proxied = async move {
+            let _in_flight = in_flight;
+            let _permit = permit.transpose().map_err(|_| Error::Overloaded)?;
+            let req = match rewrite {
+                Rewrite::Path(rewrite) => {
+                    let (mut req, rewritten) = rewrite.await;
+                    if let Some(Denied(path)) = req.extensions_mut().remove() {
+                        return Err(Error::Forbidden(path));
+                    }
+                    rewritten
+                        .and_then(|_| config.apply_base_path(&mut req))
+                        .map_err(Error::InvalidUri)?;
+                    req
+                }
+                Rewrite::Mapped(req, mapped) => {
+                    mapped.map_err(Error::InvalidUri)?;
+                    *req
+                }
+            };
+            if let Some(last) = &config.last_uri {
+                *last.lock().unwrap_or_else(PoisonError::into_inner) = Some(req.uri().clone());
+            }
+            let mut req = req;
+            if let Some(public) = &public_host {
+                let uri = req.uri().clone();
+                redirect::rewrite_origin(req.headers_mut(), public, &uri);
+            }
+            let upstream = started.as_ref().map(|_| req.uri().clone());
+
+            let cache = config
+                .cache
+                .as_ref()
+                .and_then(|cache| Some((cache, cache::key(&req, &config.cache_vary)?)));
+            let cached = cache
+                .as_ref()
+                .and_then(|(cache, key)| cache.get(key, req.headers()));
+            let flight = match (&cached, &config.singleflight) {
+                (None, Some(flights)) => flights.join(&req, &config.cache_vary),
+                _ => None,
+            };
+            let (leader, shared) = match flight {
+                Some(flight) => flight.wait().await,
+                None => (None, None),
+            };
+            let res = match cached.or(shared) {
+                Some(mut res) => {
+                    if let Some((name, id)) = request_id {
+                        res.headers_mut().insert(name, id);
+                    }
+                    Ok(res)
+                }
+                None => {
+                    #[cfg(feature = "compression")]
+                    let req = match (config.compress_request, replay) {
+                        (Some(encoding), Some(replay)) => {
+                            crate::compress::compress(req, encoding, &config, replay).await?
+                        }
+                        _ => req,
+                    };
+                    let req = match (&config.mirror, replay) {
+                        (Some(mirror), Some(replay)) => {
+                            mirror_to(&client, req, mirror, &config, replay).await?
+                        }
+                        _ => req,
+                    };
+                    let req = match &config.body_transform {
+                        Some(transform) => {
+                            map_body_request(req, |req| transform.transform_request(req))
+                        }
+                        None => req,
+                    };
+                    let req = match &config.request_trailers {
+                        Some(trailers) => trailers::append(req, trailers, &config.executor),
+                        None => req,
+                    };
+                    let res = respond(&client, req, &config, replay, request_id, on_upgrade);
+                    #[cfg(feature = "proxy-protocol")]
+                    let res = crate::proxy_protocol::scope(client_addr, res);
+                    let res = res.await;
+                    if let Some(admission) = admission {
+                        match &res {
+                            Ok(res) => admission.record(!res.status().is_server_error()),
+                            Err(e) if is_upstream_failure(e) => admission.record(false),
+                            // Caused by the client or the configuration, not by the upstream.
+                            Err(_) => {}
+                        }
+                    }
+                    let res = match (res, cache) {
+                        (Ok(res), Some((cache, key))) => {
+                            let budget = config.in_flight_bytes.as_ref();
+                            let stored = cache.store(key, res, &config.cache_vary, budget);
+                            config.within_body_timeout(stored).await
+                        }
+                        (res, _) => res,
+                    };
+                    match leader {
+                        Some(leader) => leader.share(res, &config).await,
+                        None => res,
+                    }
+                }
+            };
+
+            let res = match (res, original_uri) {
+                (Ok(mut res), Some(uri)) => {
+                    res.extensions_mut().insert(OriginalUri(uri));
+                    Ok(res)
+                }
+                (res, _) => res,
+            };
+
+            if let (Some(logger), Some((start, method, path)), Some(upstream)) =
+                (&config.access_log, started, upstream)
+            {
+                let res = res.as_ref().ok();
+                logger.log(&AccessLog {
+                    method,
+                    path,
+                    upstream,
+                    status: res.map(Response::status),
+                    bytes: res.and_then(|res| res.body().size_hint().exact()),
+                    duration: start.elapsed(),
+                });
+            }
+            res
+        };
+        let inner = Box::pin(async move {
+            let res = proxied.await;
+            let res = match res {
+                Ok(res) if head_as_get => Ok(strip_body(res)),
+                res => res,
+            };
+            stats.record(res.as_ref().ok().map(Response::status), received.elapsed());
+            res
+        });
         Self { inner }
     }
+
+    /// Fails with `err` before sending the request, still counting it in the [stats](crate::Stats)
+    /// but not in the latency.
+    fn rejected(config: &Config, err: Error) -> Self {
+        config.stats.record_rejected();
+        Self {
+            inner: Box::pin(future::ready(Err(err))),
+        }
+    }
+}
+
+type Acquire = Pin<Box<dyn Future<Output = Result<OwnedSemaphorePermit, AcquireError>> + Send>>;
+
+/// The capacity under [`max_concurrency()`](crate::ReusedServiceBuilder::max_concurrency),
+/// reserved by `poll_ready()` for the next `call()`.
+///
+/// A clone starts without reservation.
+#[derive(Default)]
+pub(crate) struct Readiness {
+    // `Mutex` makes the future `Sync`, without locking in effect since it is accessed by `&mut`.
+    acquiring: Option<Mutex<Acquire>>,
+    reserved: Option<OwnedSemaphorePermit>,
+}
+
+/// A permit passed from `call()` to [`RevProxyFuture`] via the request extensions.
+struct Reserved(OwnedSemaphorePermit);
+
+impl Clone for Readiness {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+impl fmt::Debug for Readiness {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Readiness")
+            .field("reserved", &self.reserved.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Readiness {
+    /// Waits until a permit of the semaphore of `config` is available, if any.
+    ///
+    /// An open circuit does not make this wait, so that `call()` fails fast with
+    /// [`Error::CircuitOpen`].
+    pub(crate) fn poll_ready(&mut self, config: &Config, cx: &mut Context<'_>) -> Poll<()> {
+        let sem = match &config.concurrency {
+            Some(sem) if self.reserved.is_none() => sem,
+            _ => return Poll::Ready(()),
+        };
+        let acquiring = self
+            .acquiring
+            .get_or_insert_with(|| Mutex::new(Box::pin(sem.clone().acquire_owned())));
+        let acquiring = acquiring.get_mut().unwrap_or_else(PoisonError::into_inner);
+        let acquired = ready!(acquiring.as_mut().poll(cx));
+        self.acquiring = None;
+        // The semaphore is never closed.
+        self.reserved = acquired.ok();
+        Poll::Ready(())
+    }
+
+    /// Hands the reserved permit, if any, to the request.
+    pub(crate) fn reserve<B>(&mut self, req: &mut Request<B>) {
+        if let Some(permit) = self.reserved.take() {
+            req.extensions_mut().insert(Reserved(permit));
+        }
+    }
+}
+
+/// Either rewrites the path, or has already replaced the whole URI by `map_uri`.
+enum Rewrite<Fut, B> {
+    Path(Fut),
+    Mapped(Box<Request<B>>, Result<(), HttpError>),
 }
 
 impl Future for RevProxyFuture {
     type Output = Result<Result<Response<Body>, Error>, Infallible>;
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        match &mut self.inner {
-            Ok(fut) => match Future::poll(Pin::new(fut), cx) {
-                Poll::Ready(res) => Poll::Ready(Ok(res.map_err(Error::RequestFailed))),
-                Poll::Pending => Poll::Pending,
-            },
-            Err(e) => match e.take() {
-                Some(e) => Poll::Ready(Ok(Err(Error::InvalidUri(e)))),
-                None => unreachable!("RevProxyFuture::poll() is called after ready"),
-            },
+        self.inner.as_mut().poll(cx).map(Ok)
+    }
+}
+
+async fn respond<C, B>(
+    client: &Sender<C, B>,
+    req: Request<B>,
+    config: &Config,
+    replay: Option<fn(Bytes) -> B>,
+    request_id: Option<(HeaderName, HeaderValue)>,
+    on_upgrade: Option<OnUpgrade>,
+) -> Result<Response<Body>, Error>
+where
+    C: Connect + Clone + Send + Sync + 'static,
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<BoxErr>,
+{
+    let rewrites_location = config.rewrite_location_relative || config.public_base.is_some();
+    let upstream = rewrites_location.then(|| req.uri().clone());
+    let mut res = send(client, req, config, replay).await?;
+    if let Some(max) = config.max_response_headers {
+        if res.headers().len() > max {
+            // Dropping the response closes the connection, rather than reading more from it.
+            return Err(Error::UpstreamHeaders(max));
+        }
+    }
+    if res.status() == StatusCode::SWITCHING_PROTOCOLS {
+        match (&config.on_upgrade, on_upgrade) {
+            (Some(f), Some(client_upgrade)) => {
+                let upstream_upgrade = hyper::upgrade::on(&mut res);
+                upgrade::spawn(
+                    &config.executor,
+                    f.clone(),
+                    client_upgrade,
+                    upstream_upgrade,
+                );
+            }
+            // Otherwise the client would hang on the connection not passed on.
+            _ => return Err(Error::UpgradeUnsupported),
         }
+    } else if let Some(transform) = &config.body_transform {
+        res = transform.transform_response(res);
     }
+    if let Some(limit) = config.buffer_response {
+        // Server-sent events must reach the client as they arrive, and `304` and `204` have no
+        // body to measure.
+        if !is_event_stream(&res) && !has_no_body(res.status()) {
+            let buffered = buffer(res, limit, config.in_flight_bytes.as_ref());
+            res = config.within_body_timeout(buffered).await?;
+        }
+    }
+    config.process_response(&mut res);
+    match (upstream, &config.public_base) {
+        (Some(upstream), Some(public)) => {
+            let base_path = config.base_path.as_deref();
+            redirect::publicize_location(res.headers_mut(), &upstream, public, base_path);
+        }
+        (Some(upstream), None) => redirect::relativize_location(res.headers_mut(), &upstream),
+        (None, _) => {}
+    }
+    if let Some((name, id)) = request_id {
+        res.headers_mut().insert(name, id);
+    }
+    if let Some(on_response) = &config.on_response {
+        res = on_response(res).await;
+    }
+    Ok(res)
+}
+
+async fn send<C, B>(
+    client: &Sender<C, B>,
+    req: Request<B>,
+    config: &Config,
+    replay: Option<fn(Bytes) -> B>,
+) -> Result<Response<Body>, Error>
+where
+    C: Connect + Clone + Send + Sync + 'static,
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<BoxErr>,
+{
+    let resends = config.max_redirects.is_some() || config.retry.max > 0;
+    let replay = match replay {
+        Some(replay) if resends && is_replayable(req.body(), config) => replay,
+        _ if !config.count_request_body => {
+            return client.request(req).await;
+        }
+        _ => {
+            let failure = BodyFailure::default();
+            let req = map_body_request(req, |req| count_body(req, &failure));
+            return client.request(req).await.map_err(|e| {
+                let failure = failure
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .take();
+                match failure {
+                    Some((transferred, source)) => Error::BodyStream {
+                        transferred,
+                        source,
+                    },
+                    None => e,
+                }
+            });
+        }
+    };
+
+    let (mut parts, body) = req.into_parts();
+    let budget = config.in_flight_bytes.as_ref();
+    let mut body = collect(body, config.replay_body_limit, budget).await?;
+    set_content_length(&mut parts.headers, body.len());
+    let Parts {
+        mut method,
+        mut uri,
+        version,
+        mut headers,
+        extensions,
+        ..
+    } = parts;
+    let mut extensions = Some(extensions);
+    let origin = uri.authority().cloned();
+    let mut visited = vec![uri.clone()];
+
+    for redirected in 0.. {
+        let make = || {
+            let mut req = Request::new(replay(body.clone()));
+            *req.method_mut() = method.clone();
+            *req.uri_mut() = uri.clone();
+            *req.version_mut() = version;
+            *req.headers_mut() = headers.clone();
+            if let Some(extensions) = extensions.take() {
+                *req.extensions_mut() = extensions;
+            }
+            req
+        };
+
+        let res = request_with_retries(client, &config.retry, make).await?;
+        let max_redirects = match config.max_redirects {
+            Some(max) => max,
+            None => return Ok(res),
+        };
+        let location = match redirect::location(&res, &uri) {
+            Some(location) => location,
+            None => return Ok(res),
+        };
+        let cross_host = location.authority() != origin.as_ref();
+        if cross_host && !config.cross_host_redirects {
+            return Ok(res);
+        }
+        if redirected == max_redirects {
+            return Err(Error::TooManyRedirects(max_redirects));
+        }
+        if visited.contains(&location) {
+            return Err(Error::RedirectLoop(location));
+        }
+        visited.push(location.clone());
+
+        if redirect::turns_into_get(res.status(), &method) {
+            method = Method::GET;
+            body = Bytes::new();
+            headers.remove(header::CONTENT_LENGTH);
+            headers.remove(header::CONTENT_TYPE);
+            headers.remove(header::TRANSFER_ENCODING);
+        }
+        if cross_host {
+            headers.remove(header::AUTHORIZATION);
+            headers.remove(header::COOKIE);
+            headers.remove(header::PROXY_AUTHORIZATION);
+        }
+        uri = location;
+    }
+    unreachable!("the loop returns after at most `max_redirects` redirects")
+}
+
+/// The failure of a streamed request body, and the bytes sent before it.
+type BodyFailure = Arc<Mutex<Option<(usize, BoxErr)>>>;
+
+/// Counts the bytes of the body of `req` as hyper reads them, so that a failure of the body is
+/// reported as [`Error::BodyStream`] with the count, rather than as a failed request, see
+/// [`count_request_body()`](crate::ReusedServiceBuilder::count_request_body).
+///
+/// The body is left as is if it may have trailers, *i.e.* an HTTP/2 request or one with
+/// `TE: trailers`, as set by [`request_trailers()`](crate::ReusedServiceBuilder::request_trailers),
+/// since a wrapped body drops them. Its `Content-Length`, if any, still frames the request.
+///
+/// A wrapped body has no size of its own, so the exact size of `req`, if known, is set into
+/// `Content-Length` in advance, rather than sending the body chunked.
+fn count_body(req: Request<Body>, failure: &BodyFailure) -> Request<Body> {
+    let trailers = req.version() >= Version::HTTP_2 || req.headers().contains_key(header::TE);
+    if trailers || req.body().is_end_stream() {
+        return req;
+    }
+    let (mut parts, body) = req.into_parts();
+    let framed = parts.headers.contains_key(header::CONTENT_LENGTH)
+        || parts.headers.contains_key(header::TRANSFER_ENCODING);
+    if !framed {
+        if let Some(len) = body.size_hint().exact() {
+            parts.headers.insert(header::CONTENT_LENGTH, len.into());
+        }
+    }
+    let counted = CountedBody {
+        inner: body,
+        transferred: 0,
+        failure: failure.clone(),
+    };
+    Request::from_parts(parts, Body::wrap_stream(counted))
+}
+
+/// A body passing the chunks of `inner` through, and recording its failure into `failure`.
+struct CountedBody {
+    inner: Body,
+    transferred: usize,
+    failure: BodyFailure,
+}
+
+impl HttpBody for CountedBody {
+    type Data = Bytes;
+    type Error = BoxErr;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Bytes, BoxErr>>> {
+        match ready!(Pin::new(&mut self.inner).poll_data(cx)) {
+            Some(Ok(chunk)) => {
+                self.transferred += chunk.len();
+                Poll::Ready(Some(Ok(chunk)))
+            }
+            Some(Err(e)) => {
+                let mut failure = self.failure.lock().unwrap_or_else(PoisonError::into_inner);
+                *failure = Some((self.transferred, e.into()));
+                Poll::Ready(Some(Err("the request body failed".into())))
+            }
+            None => Poll::Ready(None),
+        }
+    }
+
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, BoxErr>> {
+        Pin::new(&mut self.inner)
+            .poll_trailers(cx)
+            .map_err(Into::into)
+    }
+}
+
+impl futures_core::Stream for CountedBody {
+    type Item = Result<Bytes, BoxErr>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.poll_data(cx)
+    }
+}
+
+/// Sends the request made by `make`, and sends a new one while it fails to connect and a retry
+/// remains, after the backoff if any.
+async fn request_with_retries<C, B>(
+    client: &Sender<C, B>,
+    retry: &Retry,
+    mut make: impl FnMut() -> Request<B>,
+) -> Result<Response<Body>, Error>
+where
+    C: Connect + Clone + Send + Sync + 'static,
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<BoxErr>,
+{
+    let mut attempt = 0;
+    loop {
+        match client.request(make()).await {
+            Err(e) if is_connect_failure(&e) && attempt < retry.max => {
+                log::debug!("Retrying after a connection failure: {e}");
+                if let Some(backoff) = &retry.backoff {
+                    tokio::time::sleep(backoff.delay(attempt)).await;
+                }
+                attempt += 1;
+            }
+            res => return res,
+        }
+    }
+}
+
+/// Sends a copy of `req` to `authority` in a new task, and returns `req` with the body buffered.
+async fn mirror_to<C, B>(
+    client: &Sender<C, B>,
+    req: Request<B>,
+    authority: &Authority,
+    config: &Config,
+    replay: fn(Bytes) -> B,
+) -> Result<Request<B>, Error>
+where
+    C: Connect + Clone + Send + Sync + 'static,
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<BoxErr>,
+{
+    if !is_replayable(req.body(), config) {
+        log::debug!("Not mirrored: the body may exceed the limit");
+        return Ok(req);
+    }
+    let (mut parts, body) = req.into_parts();
+    let budget = config.in_flight_bytes.as_ref();
+    let body = collect(body, config.replay_body_limit, budget).await?;
+    set_content_length(&mut parts.headers, body.len());
+
+    let mut uri = parts.uri.clone().into_parts();
+    uri.authority = Some(authority.clone());
+    let mut mirrored = Request::new(replay(body.clone()));
+    *mirrored.method_mut() = parts.method.clone();
+    *mirrored.uri_mut() = Uri::from_parts(uri).map_err(|e| Error::InvalidUri(e.into()))?;
+    *mirrored.version_mut() = parts.version;
+    *mirrored.headers_mut() = parts.headers.clone();
+
+    let client = client.clone();
+    config.executor.spawn(async move {
+        match client.request(mirrored).await {
+            Ok(res) => log::debug!("Mirrored: {}", res.status()),
+            Err(e) => log::warn!("Mirror failed: {e}"),
+        }
+    });
+    Ok(Request::from_parts(parts, replay(body)))
+}
+
+/// Collects the body up to `limit` bytes, so that the response has an exact `Content-Length`.
+async fn buffer(
+    res: Response<Body>,
+    limit: usize,
+    budget: Option<&Arc<Semaphore>>,
+) -> Result<Response<Body>, Error> {
+    let (mut parts, body) = res.into_parts();
+    if body.size_hint().lower() > limit as u64 {
+        return Err(Error::BodyTooLarge(limit));
+    }
+    let body = collect(body, limit, budget).await?;
+
+    parts.headers.remove(header::TRANSFER_ENCODING);
+    parts
+        .headers
+        .insert(header::CONTENT_LENGTH, body.len().into());
+    Ok(Response::from_parts(parts, body.into()))
+}
+
+/// Sets `Content-Length` of a body buffered into `len` bytes, in place of the framing of
+/// the original body, *i.e.* a stale `Content-Length` or `Transfer-Encoding`. An empty body
+/// framed by neither is left so.
+pub(crate) fn set_content_length(headers: &mut HeaderMap, len: usize) {
+    let framed = headers.remove(header::TRANSFER_ENCODING).is_some()
+        || headers.contains_key(header::CONTENT_LENGTH);
+    if len > 0 || framed {
+        headers.insert(header::CONTENT_LENGTH, len.into());
+    }
+}
+
+/// Drops the body of the response to a `HEAD` sent as `GET`, keeping its length in
+/// `Content-Length`.
+fn strip_body(res: Response<Body>) -> Response<Body> {
+    let (mut parts, body) = res.into_parts();
+    if !parts.headers.contains_key(header::CONTENT_LENGTH) {
+        if let Some(len) = body.size_hint().exact() {
+            parts.headers.insert(header::CONTENT_LENGTH, len.into());
+        }
+    }
+    Response::from_parts(parts, Body::empty())
+}
+
+/// Reads the whole body, up to `limit` bytes.
+///
+/// The bytes are reserved against `budget`, if any, until the returned `Bytes` and all its clones
+/// are dropped. If the budget runs out, this fails with [`Error::Overloaded`].
+///
+/// A failure of the stream results in [`Error::BodyStream`] with the bytes read so far.
+pub(crate) async fn collect<B>(
+    body: B,
+    limit: usize,
+    budget: Option<&Arc<Semaphore>>,
+) -> Result<Bytes, Error>
+where
+    B: HttpBody,
+    B::Error: Into<BoxErr>,
+{
+    let mut body = pin!(body);
+    let mut buf = Vec::new();
+    let mut reserved: Option<OwnedSemaphorePermit> = None;
+    while let Some(chunk) = body.data().await {
+        let mut chunk = chunk.map_err(|e| Error::BodyStream {
+            transferred: buf.len(),
+            source: e.into(),
+        })?;
+        if buf.len() + chunk.remaining() > limit {
+            return Err(Error::BodyTooLarge(limit));
+        }
+        if let Some(budget) = budget {
+            let len = u32::try_from(chunk.remaining()).map_err(|_| Error::Overloaded)?;
+            let permit = Arc::clone(budget)
+                .try_acquire_many_owned(len)
+                .map_err(|_| Error::Overloaded)?;
+            match &mut reserved {
+                Some(reserved) => reserved.merge(permit),
+                None => reserved = Some(permit),
+            }
+        }
+        while chunk.has_remaining() {
+            let part = chunk.chunk();
+            let len = part.len();
+            buf.extend_from_slice(part);
+            chunk.advance(len);
+        }
+    }
+    Ok(match reserved {
+        Some(permit) => Bytes::from_owner(Budgeted {
+            buf,
+            _permit: permit,
+        }),
+        None => buf.into(),
+    })
+}
+
+/// Buffered bytes holding their reservation of
+/// [`max_in_flight_bytes()`](crate::ReusedServiceBuilder::max_in_flight_bytes).
+struct Budgeted {
+    buf: Vec<u8>,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl AsRef<[u8]> for Budgeted {
+    fn as_ref(&self) -> &[u8] {
+        &self.buf
+    }
+}
+
+/// The host the client addressed, for [`redirect::rewrite_origin()`].
+fn public_host<B>(req: &Request<B>) -> Option<String> {
+    match req.headers().get(header::HOST) {
+        Some(host) => host.to_str().ok().map(str::to_owned),
+        None => req.uri().authority().map(|authority| authority.to_string()),
+    }
+}
+
+/// Whether `err` is a failure of the upstream, counted by the
+/// [`circuit_breaker()`](crate::ReusedServiceBuilder::circuit_breaker), rather than one caused by
+/// the client, *e.g.* an aborted request body.
+fn is_upstream_failure(err: &Error) -> bool {
+    err.is_connect()
+        || err.is_timeout()
+        || matches!(err, Error::UpstreamHeaders(_) | Error::Http3(_))
+}
+
+/// Whether `err` happened before a connection is established, including the ones of hyper which
+/// [`Error::from_hyper()`] cannot classify.
+fn is_connect_failure(err: &Error) -> bool {
+    err.is_connect() || matches!(err, Error::RequestFailed(e) if e.is_connect())
+}
+
+fn has_no_body(status: StatusCode) -> bool {
+    status == StatusCode::NOT_MODIFIED || status == StatusCode::NO_CONTENT
+}
+
+pub(crate) fn is_event_stream(res: &Response<Body>) -> bool {
+    res.headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .is_some_and(|mime| mime.trim().eq_ignore_ascii_case("text/event-stream"))
+}
+
+/// The total length of the names and values of `headers`.
+fn header_bytes(headers: &HeaderMap) -> usize {
+    headers
+        .iter()
+        .map(|(name, value)| name.as_str().len() + value.len())
+        .sum()
+}
+
+fn is_replayable<B: HttpBody>(body: &B, config: &Config) -> bool {
+    body.size_hint()
+        .upper()
+        .is_some_and(|len| len <= config.replay_body_limit as u64)
 }
@@ -0,0 +1,58 @@
+use crate::reused::Builder;
+use crate::rewrite::PathRewriter;
+
+use axum::response::{IntoResponse, Response};
+
+use http::Request;
+
+use hyper::body::Body;
+use hyper::client::connect::Connect;
+
+use std::future::Future;
+use std::pin::Pin;
+
+/// An axum handler proxying the requests by a service built from `builder` with `path`, for
+/// `.route("/x", get(proxy_handler(...)))` instead of `route_service`.
+///
+/// Unlike a service, a handler goes through the method routing and the
+/// [`Handler`](axum::handler::Handler) layers of axum. The response is the one of the upstream,
+/// or an [`Error`](crate::Error) converted by [`IntoResponse`].
+///
+/// The rewriter is cloned for each request, as in
+/// [`ReusedService::call_owned()`](crate::ReusedService::call_owned).
+///
+/// ```
+/// use reverse_proxy_service::{proxy_handler, TrimPrefix};
+///
+/// use axum::routing::get;
+/// use axum::Router;
+///
+/// let host = reverse_proxy_service::builder_http("example.com").unwrap();
+/// let app: Router = Router::new()
+///     .route("/users/*path", get(proxy_handler(&host, TrimPrefix("/users"))))
+///     .route("/healthcheck", get(proxy_handler(&host, TrimPrefix("/healthcheck"))));
+/// ```
+pub fn proxy_handler<Pr, C>(
+    builder: &Builder<C, Body>,
+    path: Pr,
+) -> impl Fn(Request<Body>) -> Pin<Box<dyn Future<Output = Response> + Send>>
+       + Clone
+       + Send
+       + Sync
+       + 'static
+where
+    Pr: PathRewriter + Clone + Send + Sync + 'static,
+    C: Connect + Clone + Send + Sync + 'static,
+{
+    let svc = builder.build(path);
+    move |req| {
+        let res = svc.call_owned(req);
+        Box::pin(async move {
+            match res.await {
+                Ok(Ok(res)) => res.into_response(),
+                Ok(Err(e)) => e.into_response(),
+                Err(infallible) => match infallible {},
+            }
+        })
+    }
+}
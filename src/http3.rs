@@ -0,0 +1,371 @@
+use crate::Error;
+
+use http::header::{self, HeaderMap, HeaderName, HeaderValue};
+use http::uri::{Authority, Scheme};
+use http::{Request, Response, StatusCode};
+
+use hyper::body::{Body, Buf, Bytes, HttpBody};
+
+use h3::client::SendRequest;
+use h3_quinn::OpenStreams;
+use quic_rustls::RootCertStore;
+use quinn::crypto::rustls::QuicClientConfig;
+use quinn::{ClientConfig, Endpoint};
+
+use std::collections::HashMap;
+use std::fmt;
+use std::future::{poll_fn, Future};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::pin::{pin, Pin};
+use std::sync::{Arc, Mutex, PoisonError, Weak};
+
+type BoxErr = Box<dyn std::error::Error + Send + Sync>;
+type BoxFuture = Pin<Box<dyn Future<Output = Result<Response<Body>, Error>> + Send>>;
+
+/// The ALPN protocol of HTTP/3.
+const ALPN: &[u8] = b"h3";
+
+/// Client sending the requests over HTTP/3, see
+/// [`http3_default()`](crate::client::http3_default).
+///
+/// A QUIC connection is opened per authority and shared by the requests to it, until the upstream
+/// closes it. The UDP socket is bound on the first request, so that the client can be built
+/// outside of a runtime.
+#[derive(Clone)]
+pub struct Http3Client {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    tls: ClientConfig,
+    endpoint: Mutex<Option<Endpoint>>,
+    conns: Mutex<HashMap<Authority, Conn>>,
+}
+
+struct Conn {
+    id: usize,
+    send: SendRequest<OpenStreams, Bytes>,
+}
+
+impl fmt::Debug for Http3Client {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let conns = self
+            .inner
+            .conns
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        f.debug_struct("Http3Client")
+            .field("conns", &conns.keys().collect::<Vec<_>>())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Http3Client {
+    /// Trusts the certificates issued by `roots`. Only TLS 1.3 is offered, since QUIC mandates it.
+    pub(crate) fn new(roots: RootCertStore) -> Self {
+        let provider = Arc::new(quic_rustls::crypto::ring::default_provider());
+        let mut tls = quic_rustls::ClientConfig::builder_with_provider(provider)
+            .with_protocol_versions(&[&quic_rustls::version::TLS13])
+            .expect("ring supports TLS 1.3")
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        tls.alpn_protocols = vec![ALPN.to_vec()];
+        let tls = QuicClientConfig::try_from(tls).expect("ring has the initial suite of QUIC");
+        Self {
+            inner: Arc::new(Inner {
+                tls: ClientConfig::new(Arc::new(tls)),
+                endpoint: Mutex::new(None),
+                conns: Mutex::new(HashMap::new()),
+            }),
+        }
+    }
+
+    pub(crate) fn webpki_roots() -> Self {
+        Self::new(RootCertStore::from_iter(
+            webpki_roots::TLS_SERVER_ROOTS.iter().cloned(),
+        ))
+    }
+
+    pub(crate) fn request<B>(&self, req: Request<B>) -> BoxFuture
+    where
+        B: HttpBody + Send + 'static,
+        B::Data: Send,
+        B::Error: Into<BoxErr>,
+    {
+        let client = self.clone();
+        Box::pin(async move { client.send(req).await })
+    }
+
+    async fn send<B>(&self, req: Request<B>) -> Result<Response<Body>, Error>
+    where
+        B: HttpBody + Send,
+        B::Error: Into<BoxErr>,
+    {
+        let (parts, body) = req.into_parts();
+        if parts.uri.scheme() != Some(&Scheme::HTTPS) {
+            return Err(Error::UnsupportedScheme(parts.uri.scheme().cloned()));
+        }
+        let authority =
+            parts.uri.authority().cloned().ok_or_else(|| {
+                Error::InvalidAuthority(format!("`{}` has no authority", parts.uri))
+            })?;
+
+        let mut head = http_1::Request::builder()
+            .method(parts.method.as_str())
+            .uri(parts.uri.to_string())
+            .body(())
+            .map_err(http3_error)?;
+        *head.headers_mut() = to_h3_headers(&parts.headers)?;
+
+        let mut send = self.connect(&authority).await?;
+        let mut stream = match send.send_request(head).await {
+            Ok(stream) => stream,
+            Err(e) => {
+                self.evict(&authority, None);
+                return Err(http3_error(e));
+            }
+        };
+
+        let mut body = pin!(body);
+        let mut transferred = 0;
+        loop {
+            // Not `while let`, which keeps the error of the body, maybe not `Send`, across `await`.
+            let chunk = match body.data().await {
+                Some(chunk) => chunk.map_err(|e| Error::BodyStream {
+                    transferred,
+                    source: e.into(),
+                }),
+                None => break,
+            };
+            let mut chunk = chunk?;
+            transferred += chunk.remaining();
+            let chunk = chunk.copy_to_bytes(chunk.remaining());
+            stream.send_data(chunk).await.map_err(http3_error)?;
+        }
+        let trailers = body.trailers().await.map_err(|e| Error::BodyStream {
+            transferred,
+            source: e.into(),
+        })?;
+        match trailers {
+            Some(trailers) => stream.send_trailers(to_h3_headers(&trailers)?).await,
+            None => stream.finish().await,
+        }
+        .map_err(http3_error)?;
+
+        let head = stream.recv_response().await.map_err(http3_error)?;
+        let (tx, body) = Body::channel();
+        tokio::spawn(async move {
+            let mut tx = tx;
+            loop {
+                match stream.recv_data().await {
+                    Ok(Some(mut chunk)) => {
+                        let chunk = chunk.copy_to_bytes(chunk.remaining());
+                        if tx.send_data(chunk).await.is_err() {
+                            stream.stop_sending(h3::error::Code::H3_REQUEST_CANCELLED);
+                            return;
+                        }
+                    }
+                    Ok(None) => break,
+                    Err(e) => {
+                        log::debug!("HTTP/3 response body failed: {e}");
+                        tx.abort();
+                        return;
+                    }
+                }
+            }
+            match stream.recv_trailers().await {
+                Ok(Some(trailers)) => match from_h3_headers(&trailers) {
+                    Ok(trailers) => {
+                        let _ = tx.send_trailers(trailers).await;
+                    }
+                    Err(_) => tx.abort(),
+                },
+                Ok(None) => {}
+                Err(e) => {
+                    log::debug!("HTTP/3 response trailers failed: {e}");
+                    tx.abort();
+                }
+            }
+        });
+
+        // The version is left HTTP/1.1, which any server can write.
+        let mut res = Response::new(body);
+        *res.status_mut() = StatusCode::from_u16(head.status().as_u16()).map_err(http3_error)?;
+        *res.headers_mut() = from_h3_headers(head.headers())?;
+        Ok(res)
+    }
+
+    /// The connection to `authority`, opened if none is open.
+    async fn connect(
+        &self,
+        authority: &Authority,
+    ) -> Result<SendRequest<OpenStreams, Bytes>, Error> {
+        if let Some(conn) = self.conns().get(authority) {
+            return Ok(conn.send.clone());
+        }
+
+        let host = authority
+            .host()
+            .trim_start_matches('[')
+            .trim_end_matches(']');
+        let port = authority.port_u16().unwrap_or(443);
+        let addr = tokio::net::lookup_host((host, port))
+            .await
+            .map_err(http3_error)?
+            .next()
+            .ok_or_else(|| http3_error(format!("`{host}` has no address")))?;
+        let conn = self
+            .endpoint(addr)?
+            .connect_with(self.inner.tls.clone(), addr, host)
+            .map_err(http3_error)?
+            .await
+            .map_err(http3_error)?;
+        let id = conn.stable_id();
+        let (mut driver, send) = h3::client::new(h3_quinn::Connection::new(conn))
+            .await
+            .map_err(http3_error)?;
+
+        let inner = Arc::downgrade(&self.inner);
+        let closed = authority.clone();
+        tokio::spawn(async move {
+            let e = poll_fn(|cx| driver.poll_close(cx)).await;
+            log::debug!("HTTP/3 connection to {closed} closed: {e}");
+            if let Some(inner) = Weak::upgrade(&inner) {
+                Self { inner }.evict(&closed, Some(id));
+            }
+        });
+
+        let conn = Conn {
+            id,
+            send: send.clone(),
+        };
+        self.conns().insert(authority.clone(), conn);
+        Ok(send)
+    }
+
+    /// Forgets the connection to `authority`, if it is still the one of `id`.
+    fn evict(&self, authority: &Authority, id: Option<usize>) {
+        let mut conns = self.conns();
+        if id.is_none_or(|id| conns.get(authority).is_some_and(|conn| conn.id == id)) {
+            conns.remove(authority);
+        }
+    }
+
+    fn conns(&self) -> std::sync::MutexGuard<'_, HashMap<Authority, Conn>> {
+        self.inner
+            .conns
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// The endpoint, bound on the unspecified address of the family of `addr` at the first call.
+    fn endpoint(&self, addr: SocketAddr) -> Result<Endpoint, Error> {
+        let mut endpoint = self
+            .inner
+            .endpoint
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if let Some(endpoint) = &*endpoint {
+            return Ok(endpoint.clone());
+        }
+        // A socket on `[::]` reaches IPv4 addresses as well, unless IPv6 is disabled.
+        let bound = Endpoint::client((Ipv6Addr::UNSPECIFIED, 0).into());
+        let bound = match bound {
+            Err(_) if addr.is_ipv4() => Endpoint::client((Ipv4Addr::UNSPECIFIED, 0).into()),
+            bound => bound,
+        };
+        let bound = bound.map_err(http3_error)?;
+        *endpoint = Some(bound.clone());
+        Ok(bound)
+    }
+}
+
+fn http3_error(e: impl Into<BoxErr>) -> Error {
+    Error::Http3(e.into())
+}
+
+/// Converts the headers to the ones of `http` 1, which `h3` uses, without the connection-specific
+/// headers HTTP/3 forbids. `Host` is dropped too, since the authority is sent in `:authority`.
+fn to_h3_headers(headers: &HeaderMap) -> Result<http_1::HeaderMap, Error> {
+    let mut converted = http_1::HeaderMap::with_capacity(headers.len());
+    for (name, value) in headers {
+        let forbidden = name == header::HOST
+            || name == header::CONNECTION
+            || name == header::TRANSFER_ENCODING
+            || name == header::UPGRADE
+            || name == "keep-alive"
+            || name == "proxy-connection"
+            || (name == header::TE && value != "trailers");
+        if forbidden {
+            continue;
+        }
+        let name = http_1::HeaderName::from_bytes(name.as_ref()).map_err(http3_error)?;
+        let value = http_1::HeaderValue::from_bytes(value.as_bytes()).map_err(http3_error)?;
+        converted.append(name, value);
+    }
+    Ok(converted)
+}
+
+fn from_h3_headers(headers: &http_1::HeaderMap) -> Result<HeaderMap, Error> {
+    let mut converted = HeaderMap::with_capacity(headers.len());
+    for (name, value) in headers {
+        let name = HeaderName::from_bytes(name.as_ref()).map_err(http3_error)?;
+        let value = HeaderValue::from_bytes(value.as_bytes()).map_err(http3_error)?;
+        converted.append(name, value);
+    }
+    Ok(converted)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_helper;
+
+    #[tokio::test]
+    async fn request() {
+        let (port, cert) = test_helper::serve_h3();
+        let mut roots = RootCertStore::empty();
+        assert!(roots.add(cert).is_ok());
+        let client = Http3Client::new(roots);
+
+        for _ in 0..2 {
+            let req = Request::post(format!("https://127.0.0.1:{port}/echo"))
+                .header(header::HOST, "example.com")
+                .header(header::CONNECTION, "keep-alive")
+                .header("x-test", "1")
+                .body(Body::from("hello"))
+                .unwrap();
+            let res = client.request(req).await;
+            assert!(res.is_ok(), "{}", res.unwrap_err());
+            let res = res.unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+            assert_eq!(res.headers()["x-header-names"], "x-test");
+            let body = hyper::body::to_bytes(res.into_body()).await;
+            assert_eq!(body.unwrap(), "POST /echo hello");
+        }
+        // The connection is reused.
+        assert_eq!(client.conns().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn untrusted_certificate() {
+        let (port, _) = test_helper::serve_h3();
+        let client = Http3Client::webpki_roots();
+        let req = Request::get(format!("https://127.0.0.1:{port}/"))
+            .body(Body::empty())
+            .unwrap();
+        let res = client.request(req).await;
+        assert!(matches!(res, Err(Error::Http3(_))));
+        assert!(client.conns().is_empty());
+    }
+
+    #[tokio::test]
+    async fn unsupported_scheme() {
+        let client = Http3Client::webpki_roots();
+        let req = Request::get("http://127.0.0.1:1/")
+            .body(Body::empty())
+            .unwrap();
+        let res = client.request(req).await;
+        assert!(matches!(res, Err(Error::UnsupportedScheme(Some(_)))));
+    }
+}
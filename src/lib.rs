@@ -1,4 +1,9 @@
 #![cfg_attr(docsrs, feature(doc_cfg))]
+// Without a protocol, the services and everything they use are compiled out.
+#![cfg_attr(
+    not(any(feature = "http1", feature = "http2")),
+    allow(dead_code, unused_imports)
+)]
 
 //! `reverse-proxy-service` is tower [`Service`s](tower_service::Service) that performs "reverse
 //! proxy" with various rewriting rules.
@@ -84,6 +89,9 @@
 //! }
 //! ```
 //!
+//! To route a proxy as a handler rather than a service, *e.g.* with `get(...)`, see
+//! [`proxy_handler()`].
+//!
 //!
 //! # Return Types
 //!
@@ -93,11 +101,68 @@
 //!
 //! The [`Error`] type implements [`IntoResponse`](axum::response::IntoResponse) if you enable the
 //! `axum`feature.
-//! It returns an empty body, with the status code `INTERNAL_SERVER_ERROR`. The description of this
-//! error will be logged out at [error](`log::error`) level in the
+//! It returns an empty body, with the status code `INTERNAL_SERVER_ERROR` (or `BAD_GATEWAY` for
+//! [`Error::Dns`], [`Error::Connect`], [`Error::Tls`], [`Error::UpgradeUnsupported`] and
+//! [`Error::UpstreamHeaders`], `SERVICE_UNAVAILABLE` for [`Error::Overloaded`],
+//! [`Error::CircuitOpen`] and [`Error::Draining`], `METHOD_NOT_ALLOWED` for
+//! [`Error::MethodNotAllowed`], `BAD_REQUEST` for [`Error::InvalidRequestHeader`] and
+//! [`Error::InvalidAuthority`], `FORBIDDEN` for [`Error::Forbidden`],
+//! `REQUEST_HEADER_FIELDS_TOO_LARGE` for [`Error::HeadersTooLarge`], and `GATEWAY_TIMEOUT` for
+//! [`Error::Timeout`]). The description of this error will be logged out at
+//! [error](`log::error`) level in the
 //! [`into_response()`](axum::response::IntoResponse::into_response()) method.
 //!
 //!
+//! # `Expect: 100-continue`
+//!
+//! The `Expect` header is forwarded to the upstream as is. Note that [`hyper::Client`] does not
+//! wait for an interim `100 Continue` response; it starts sending the body right after the
+//! headers. If the upstream rejects the request, *e.g.* with `417 Expectation Failed`, that final
+//! response is returned as the response of the service.
+//!
+//! On the other side, a hyper server (and hence axum) sends `100 Continue` to the client when the
+//! request body is first polled, that is, when the body starts streaming to the upstream.
+//!
+//!
+//! # Trailers
+//!
+//! The bodies are streamed as they are, so the trailers (*e.g.* `grpc-status` of gRPC) of both
+//! the request and the response are forwarded untouched. They are available via
+//! [`HttpBody::trailers()`](hyper::body::HttpBody::trailers) of the response body. To append
+//! trailers to the requests, see
+//! [`request_trailers()`](ReusedServiceBuilder::request_trailers).
+//!
+//! Note that hyper supports trailers only in HTTP/2, so you need the `http2` feature and a client
+//! speaking HTTP/2 to the upstream, *e.g.* one built with `http2_only(true)`.
+//!
+//!
+//! # Request bodies
+//!
+//! A request body, *e.g.* a large `multipart/form-data` upload, is streamed to the upstream chunk
+//! by chunk, and never held in memory as a whole. The only exception is
+//! [`follow_redirects()`](ReusedServiceBuilder::follow_redirects), which buffers a body to send
+//! it again only if its size is known to be at most
+//! [`replay_body_limit()`](ReusedServiceBuilder::replay_body_limit); a larger or chunked body is
+//! streamed, and a redirect is returned as is. The limits of the responses, such as
+//! [`buffer_response()`](ReusedServiceBuilder::buffer_response), do not apply to the requests.
+//! A buffered body is sent with its exact `Content-Length`, replacing the `Content-Length` or
+//! `Transfer-Encoding` of the original request.
+//!
+//!
+//! # Migrating from 0.2
+//!
+//! The constructors and builders taking an upstream authority, *e.g.* [`builder_http()`],
+//! [`ReusedService::with_http_client()`] and [`OneshotService::http_default()`], as well as
+//! [`ReusedService::set_authority()`] and [`ReusedService::set_scheme()`], return [`Error`]
+//! instead of [`http::Error`], so that an invalid authority is reported with what is wrong in
+//! [`Error::InvalidAuthority`]. A malformed scheme or authority is [`Error::InvalidUri`], which
+//! wraps the [`http::Error`] returned before. [`Error`] is `#[non_exhaustive]`, so a `match` on
+//! it needs a wildcard arm.
+//!
+//! The minimum supported Rust version is now 1.83, for `Option::is_none_or()` and the kinds of
+//! `io::Error` classifying connection errors, *e.g.* `HostUnreachable`.
+//!
+//!
 //! # Features
 //!
 //! By default only `http1` is enabled.
@@ -110,16 +175,87 @@
 //! - `rustls-webpki-roots`: uses the `hyper-rustls` crate, with the feature `webpki-roots`
 //! - `rustls-native-roots`: uses the `hyper-rustls` crate, with the feature `rustls-native-certs`
 //! - `rustls-http2`: `http2` plus `rustls`, and `rustls/http2` is enabled
-//! - `axum`: implements [`IntoResponse`](axum::response::IntoResponse) for [`Error`]
+//! - `http3`: adds [`builder_http3()`] and [`client::http3_default()`], which send the requests
+//!   over HTTP/3 with the `h3` and `quinn` crates. QUIC mandates TLS 1.3, so that the upstream
+//!   has to accept it, whichever TLS feature is on. This feature needs Rust 1.85, for `quinn`
+//! - `proxy-protocol`: adds [`ProxyProtocolConnector`](client::ProxyProtocolConnector), which
+//!   sends the PROXY protocol header to the upstream
+//! - `compression`: adds
+//!   [`compress_request()`](ReusedServiceBuilder::compress_request), with the `flate2` crate
+//! - `serde`: implements `Serialize` and `Deserialize` for [`Rewrite`] and the rewriters of
+//!   string slices, *e.g.* [`TrimPrefix`], which borrow from the input
+//! - `buffer`: adds [`ReusedService::buffered()`], which runs the service in a
+//!   [`tower::buffer::Buffer`]
+//! - `axum`: implements [`IntoResponse`](axum::response::IntoResponse) for [`Error`], and adds
+//!   [`proxy_handler()`]
 //!
 //! You must turn on either `http1`or `http2`. You cannot use the services if, for example, only
-//! the `https` feature is on.
+//! the `https` feature is on, so the compilation fails with an error telling you to enable one of
+//! them. Precisely, it fails if
+//! `all(any(feature = "nativetls", feature = "__rustls"), not(any(feature = "http1", feature = "http2")))`,
+//! where `https` enables `nativetls`, and every `rustls*` enables `__rustls`.
 //!
 //! Through this document, we use `rustls` to mean *any* of `rustls*` features unless otherwise
 //! specified.
 
+#[cfg(all(
+    any(feature = "nativetls", feature = "__rustls"),
+    not(any(feature = "http1", feature = "http2"))
+))]
+compile_error!(
+    "a TLS feature of `reverse-proxy-service` is enabled, but neither `http1` nor `http2` is; \
+     enable at least one of them, e.g. `features = [\"http1\", \"https\"]`"
+);
+
+mod access_log;
+pub use access_log::{AccessLog, AccessLogger};
+
+mod auth;
+mod balance;
+pub use balance::Balance;
+mod breaker;
+#[cfg(any(feature = "http1", feature = "http2"))]
+mod cache;
+#[cfg(all(any(feature = "http1", feature = "http2"), feature = "compression"))]
+mod compress;
+#[cfg(all(any(feature = "http1", feature = "http2"), feature = "compression"))]
+#[cfg_attr(docsrs, doc(cfg(feature = "compression")))]
+pub use compress::Encoding;
+#[cfg(any(feature = "http1", feature = "http2"))]
+mod config;
+
+mod cookie;
+pub use cookie::RewriteSetCookie;
+mod drain;
+
+mod exec;
+#[cfg(any(feature = "http1", feature = "http2"))]
+mod family;
+mod forwarded;
+#[cfg(all(any(feature = "http1", feature = "http2"), feature = "http3"))]
+mod http3;
+
+mod pool;
+#[cfg(feature = "proxy-protocol")]
+mod proxy_protocol;
+mod read_body;
+pub use read_body::AsyncReadBody;
+#[cfg(any(feature = "http1", feature = "http2"))]
+mod redirect;
+mod request_id;
+mod retry;
+mod sanitize;
+#[cfg(any(feature = "http1", feature = "http2"))]
+mod singleflight;
+mod stats;
+pub use stats::Stats;
+mod trailers;
+mod transform;
+pub use transform::BodyTransform;
+mod upgrade;
+
 mod error;
-pub use error::Error;
+pub use error::{Error, ErrorKind};
 
 #[cfg(any(feature = "http1", feature = "http2"))]
 #[cfg_attr(docsrs, doc(cfg(any(feature = "http1", feature = "http2"))))]
@@ -128,17 +264,45 @@ pub mod client;
 pub mod rewrite;
 pub use rewrite::*;
 
+#[cfg(any(feature = "http1", feature = "http2"))]
 mod future;
+#[cfg(any(feature = "http1", feature = "http2"))]
+#[cfg_attr(docsrs, doc(cfg(any(feature = "http1", feature = "http2"))))]
 pub use future::RevProxyFuture;
 
+mod maybe;
+pub use maybe::{MaybeProxy, MaybeProxyFuture, NotProxied};
+
 #[cfg(any(feature = "http1", feature = "http2"))]
 mod oneshot;
 #[cfg(any(feature = "http1", feature = "http2"))]
 #[cfg_attr(docsrs, doc(cfg(any(feature = "http1", feature = "http2"))))]
 pub use oneshot::OneshotService;
 
+#[cfg(all(any(feature = "http1", feature = "http2"), feature = "buffer"))]
+mod buffer;
+#[cfg(all(any(feature = "http1", feature = "http2"), feature = "buffer"))]
+#[cfg_attr(
+    docsrs,
+    doc(cfg(all(any(feature = "http1", feature = "http2"), feature = "buffer")))
+)]
+pub use buffer::BufferedService;
+#[cfg(all(any(feature = "http1", feature = "http2"), feature = "axum"))]
+mod handler;
 #[cfg(any(feature = "http1", feature = "http2"))]
 mod reused;
+#[cfg(all(any(feature = "http1", feature = "http2"), feature = "axum"))]
+#[cfg_attr(
+    docsrs,
+    doc(cfg(all(any(feature = "http1", feature = "http2"), feature = "axum")))
+)]
+pub use handler::proxy_handler;
+#[cfg(all(any(feature = "http1", feature = "http2"), feature = "http3"))]
+#[cfg_attr(
+    docsrs,
+    doc(cfg(all(any(feature = "http1", feature = "http2"), feature = "http3")))
+)]
+pub use reused::builder_http3;
 #[cfg(all(
     any(feature = "http1", feature = "http2"),
     any(feature = "https", feature = "nativetls")
@@ -171,22 +335,101 @@ pub use reused::Builder as ReusedServiceBuilder;
 pub use reused::ReusedService;
 #[cfg(any(feature = "http1", feature = "http2"))]
 #[cfg_attr(docsrs, doc(cfg(any(feature = "http1", feature = "http2"))))]
-pub use reused::{builder, builder_http};
+pub use reused::{
+    builder, builder_http, builder_http_bound, builder_http_keepalive, builder_http_nodelay,
+};
 
 #[cfg(test)]
 mod test_helper {
     use super::{Error, RevProxyFuture};
     use std::convert::Infallible;
+    use std::future::Future;
+    use std::net::SocketAddr;
 
     use http::StatusCode;
     use http::{Request, Response};
 
     use hyper::body::Body;
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::Server;
 
     use tower_service::Service;
 
     use mockito::Matcher;
 
+    /// Spawns an upstream server speaking both HTTP/1 and HTTP/2, and returns its address.
+    pub fn serve<F, Fut>(f: F) -> SocketAddr
+    where
+        F: Fn(Request<Body>) -> Fut + Clone + Send + Sync + 'static,
+        Fut: Future<Output = Result<Response<Body>, hyper::Error>> + Send + 'static,
+    {
+        let make_svc = make_service_fn(move |_| {
+            let f = f.clone();
+            async move { Ok::<_, Infallible>(service_fn(f)) }
+        });
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_svc);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        addr
+    }
+
+    /// Spawns an upstream server speaking HTTP/3 on `127.0.0.1`, with a self-signed certificate
+    /// for the address. Responds with the method, the path and the body of a request, and the
+    /// names of its headers in `x-header-names`. Returns the port and the certificate.
+    #[cfg(feature = "http3")]
+    pub fn serve_h3() -> (u16, quic_rustls::pki_types::CertificateDer<'static>) {
+        use hyper::body::{Buf, Bytes};
+        use quic_rustls::pki_types::{PrivateKeyDer, PrivatePkcs8KeyDer};
+        use std::sync::Arc;
+
+        let key = rcgen::generate_simple_self_signed(vec!["127.0.0.1".into()]).unwrap();
+        let cert = key.cert.der().clone();
+        let private = PrivatePkcs8KeyDer::from(key.key_pair.serialize_der());
+        let provider = Arc::new(quic_rustls::crypto::ring::default_provider());
+        let mut tls = quic_rustls::ServerConfig::builder_with_provider(provider)
+            .with_protocol_versions(&[&quic_rustls::version::TLS13])
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(vec![cert.clone()], PrivateKeyDer::Pkcs8(private))
+            .unwrap();
+        tls.alpn_protocols = vec![b"h3".to_vec()];
+        let tls = quinn::crypto::rustls::QuicServerConfig::try_from(tls).unwrap();
+        let config = quinn::ServerConfig::with_crypto(Arc::new(tls));
+        let endpoint = quinn::Endpoint::server(config, ([127, 0, 0, 1], 0).into()).unwrap();
+        let port = endpoint.local_addr().unwrap().port();
+
+        tokio::spawn(async move {
+            while let Some(incoming) = endpoint.accept().await {
+                tokio::spawn(async move {
+                    let conn = h3_quinn::Connection::new(incoming.await.unwrap());
+                    let mut conn = h3::server::Connection::<_, Bytes>::new(conn).await.unwrap();
+                    while let Ok(Some(resolver)) = conn.accept().await {
+                        let (req, mut stream) = resolver.resolve_request().await.unwrap();
+                        let mut body = Vec::new();
+                        while let Some(mut chunk) = stream.recv_data().await.unwrap() {
+                            body.extend_from_slice(&chunk.copy_to_bytes(chunk.remaining()));
+                        }
+                        let names = req.headers().keys().map(|name| name.as_str());
+                        let res = http_1::Response::builder()
+                            .header("x-header-names", names.collect::<Vec<_>>().join(","))
+                            .body(())
+                            .unwrap();
+                        stream.send_response(res).await.unwrap();
+                        let body = format!(
+                            "{} {} {}",
+                            req.method(),
+                            req.uri().path(),
+                            String::from_utf8_lossy(&body)
+                        );
+                        stream.send_data(Bytes::from(body)).await.unwrap();
+                        stream.finish().await.unwrap();
+                    }
+                });
+            }
+        });
+        (port, cert)
+    }
+
     async fn call<S, B>(
         svc: &mut S,
         req: (&str, &str, Option<&str>, B),
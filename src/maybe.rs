@@ -0,0 +1,161 @@
+use http::Request;
+
+use tower_service::Service;
+
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// A [`Service<Request<B>>`] that proxies a request only if the predicate returns `true`, and
+/// otherwise returns the request back as [`NotProxied`] without contacting the upstream.
+///
+/// This is handy as a fallback of a router, to fall through the requests not for the upstream.
+///
+/// ```
+/// # async fn run_test() {
+/// # use reverse_proxy_service::{Identity, MaybeProxy, NotProxied};
+/// # use tower_service::Service;
+/// # use hyper::body::Body;
+/// # use http::Request;
+/// let svc = reverse_proxy_service::builder_http("example.com")
+///     .unwrap()
+///     .build(Identity);
+/// let mut svc = MaybeProxy::new(svc, |req: &Request<Body>| req.uri().path().starts_with("/api"));
+///
+/// let req = Request::builder().uri("/static/app.js").body(Body::empty()).unwrap();
+/// match svc.call(req).await.unwrap() {
+///     Ok(_res) => {}
+///     Err(NotProxied(req)) => {
+///         // handle the request by yourself
+/// #       assert_eq!(req.uri(), "/static/app.js");
+///     }
+/// }
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct MaybeProxy<S, P> {
+    inner: S,
+    predicate: P,
+}
+
+impl<S, P> MaybeProxy<S, P> {
+    /// `predicate` must be `FnMut(&Request<B>) -> bool`.
+    pub fn new(inner: S, predicate: P) -> Self {
+        Self { inner, predicate }
+    }
+}
+
+/// A request which [`MaybeProxy`] did not proxy.
+#[derive(Debug)]
+pub struct NotProxied<R>(pub R);
+
+impl<R> NotProxied<R> {
+    pub fn into_inner(self) -> R {
+        self.0
+    }
+}
+
+impl<S, P, B> Service<Request<B>> for MaybeProxy<S, P>
+where
+    S: Service<Request<B>>,
+    S::Future: Unpin,
+    P: FnMut(&Request<B>) -> bool,
+{
+    type Response = Result<S::Response, NotProxied<Request<B>>>;
+    type Error = S::Error;
+    type Future = MaybeProxyFuture<S::Future, B>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<B>) -> Self::Future {
+        let inner = if (self.predicate)(&req) {
+            Inner::Proxied(self.inner.call(req))
+        } else {
+            Inner::NotProxied(Some(Box::new(req)))
+        };
+        MaybeProxyFuture { inner }
+    }
+}
+
+/// The future of [`MaybeProxy`].
+pub struct MaybeProxyFuture<F, B> {
+    inner: Inner<F, B>,
+}
+
+enum Inner<F, B> {
+    Proxied(F),
+    // Boxed since a request is much larger than the future.
+    NotProxied(Option<Box<Request<B>>>),
+}
+
+impl<F, B, T, E> Future for MaybeProxyFuture<F, B>
+where
+    F: Future<Output = Result<T, E>> + Unpin,
+{
+    type Output = Result<Result<T, NotProxied<Request<B>>>, E>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match &mut self.inner {
+            Inner::Proxied(fut) => Pin::new(fut).poll(cx).map(|res| res.map(Ok)),
+            Inner::NotProxied(req) => {
+                let req = req.take().expect("polled after completion");
+                Poll::Ready(Ok(Err(NotProxied(*req))))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::client;
+    use crate::ReusedServiceBuilder;
+
+    use http::uri::Uri;
+    use http::StatusCode;
+
+    #[tokio::test]
+    async fn maybe_proxy() {
+        let _mk = mockito::mock("GET", "/api/foo")
+            .with_body("proxied")
+            .create();
+
+        let uri = Uri::try_from(&mockito::server_url());
+        assert!(uri.is_ok());
+        let uri = uri.unwrap().into_parts();
+        let builder = ReusedServiceBuilder::from_shared(
+            client::http_default().into(),
+            uri.scheme.unwrap(),
+            uri.authority.unwrap(),
+        );
+        assert!(builder.is_ok());
+        let svc = builder.unwrap().build(crate::Identity);
+        let mut svc = MaybeProxy::new(svc, |req: &Request<String>| {
+            req.uri().path().starts_with("/api")
+        });
+
+        let req = Request::builder()
+            .uri("https://test.com/api/foo")
+            .body(String::new())
+            .unwrap();
+        let res = svc.call(req).await.unwrap();
+        assert!(res.is_ok());
+        let res = res.unwrap();
+        assert!(res.is_ok());
+        let res = res.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        // Not sent to the mock, which would respond 501.
+        let req = Request::builder()
+            .uri("https://test.com/static/app.js")
+            .body(String::from("body"))
+            .unwrap();
+        let res = svc.call(req).await.unwrap();
+        assert!(res.is_err());
+        let req = res.unwrap_err().into_inner();
+        assert_eq!(req.uri(), "https://test.com/static/app.js");
+        assert_eq!(req.body(), "body");
+    }
+}
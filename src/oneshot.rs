@@ -1,4 +1,5 @@
 use crate::client;
+use crate::config::{upstream_authority, Config};
 use crate::future::RevProxyFuture;
 use crate::rewrite::PathRewriter;
 use crate::Error;
@@ -9,7 +10,7 @@ use client::RustlsConnector;
 #[cfg(feature = "nativetls")]
 use hyper_tls::HttpsConnector as NativeTlsConnector;
 
-use http::uri::{Authority, Scheme};
+use http::uri::{Authority, Scheme, Uri};
 use http::Error as HttpError;
 use http::{Request, Response};
 
@@ -19,6 +20,8 @@ use hyper::client::{connect::Connect, Client};
 use tower_service::Service;
 
 use std::convert::Infallible;
+use std::fmt;
+use std::sync::Arc;
 use std::task::{Context, Poll};
 
 type BoxErr = Box<dyn std::error::Error + Send + Sync>;
@@ -46,6 +49,18 @@ pub struct OneshotService<Pr, C = HttpConnector, B = Body> {
     scheme: Scheme,
     authority: Authority,
     path: Pr,
+    config: Arc<Config>,
+}
+
+/// Prints the upstream and the rewriter, but not the client.
+impl<Pr: fmt::Debug, C, B> fmt::Debug for OneshotService<Pr, C, B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OneshotService")
+            .field("scheme", &self.scheme)
+            .field("authority", &self.authority)
+            .field("path", &self.path)
+            .finish_non_exhaustive()
+    }
 }
 
 impl<Pr: Clone, C: Clone, B> Clone for OneshotService<Pr, C, B> {
@@ -56,6 +71,7 @@ impl<Pr: Clone, C: Clone, B> Clone for OneshotService<Pr, C, B> {
             scheme: self.scheme.clone(),
             authority: self.authority.clone(),
             path: self.path.clone(),
+            config: self.config.clone(),
         }
     }
 }
@@ -74,20 +90,21 @@ impl<Pr, C, B> OneshotService<Pr, C, B> {
         scheme: S,
         authority: A,
         path: Pr,
-    ) -> Result<Self, HttpError>
+    ) -> Result<Self, Error>
     where
         Scheme: TryFrom<S>,
         <Scheme as TryFrom<S>>::Error: Into<HttpError>,
         Authority: TryFrom<A>,
         <Authority as TryFrom<A>>::Error: Into<HttpError>,
     {
-        let scheme = scheme.try_into().map_err(Into::into)?;
-        let authority = authority.try_into().map_err(Into::into)?;
+        let scheme = Scheme::try_from(scheme).map_err(|e| Error::InvalidUri(e.into()))?;
+        let authority = upstream_authority(authority)?;
         Ok(Self {
             client,
             scheme,
             authority,
             path,
+            config: Default::default(),
         })
     }
 }
@@ -102,17 +119,40 @@ where
     /// For the meaning of "authority", refer to the documentation of [`Uri`](http::uri::Uri).
     ///
     /// The `path` should implement [`PathRewriter`].
-    pub fn http_default<A>(authority: A, path: Pr) -> Result<Self, HttpError>
+    pub fn http_default<A>(authority: A, path: Pr) -> Result<Self, Error>
     where
         Authority: TryFrom<A>,
         <Authority as TryFrom<A>>::Error: Into<HttpError>,
     {
-        let authority = authority.try_into().map_err(Into::into)?;
+        let authority = upstream_authority(authority)?;
         Ok(Self {
             client: client::http_default(),
             scheme: Scheme::HTTP,
             authority,
             path,
+            config: Default::default(),
+        })
+    }
+
+    /// Parses an absolute `uri`, *e.g.* `http://example.com/base`, and uses
+    /// [`client::http_default()`] to build a client. A path in `uri` is prepended to every path
+    /// after the [`PathRewriter`] runs, like
+    /// [`Builder::base_path()`](crate::ReusedServiceBuilder::base_path).
+    ///
+    /// A scheme other than `http` results in [`Error::UnsupportedScheme`].
+    pub fn from_uri<U>(uri: U, path: Pr) -> Result<Self, Error>
+    where
+        Uri: TryFrom<U>,
+        <Uri as TryFrom<U>>::Error: Into<HttpError>,
+    {
+        let uri = Uri::try_from(uri).map_err(|e| Error::InvalidUri(e.into()))?;
+        let (scheme, authority, config) = Config::from_uri(uri, &[Scheme::HTTP])?;
+        Ok(Self {
+            client: client::http_default(),
+            scheme,
+            authority,
+            path,
+            config: Arc::new(config),
         })
     }
 }
@@ -131,17 +171,18 @@ where
     ///
     /// The `path` should implement [`PathRewriter`].
     #[cfg_attr(docsrs, doc(cfg(any(feature = "https", feature = "nativetls"))))]
-    pub fn https_default<A>(authority: A, path: Pr) -> Result<Self, HttpError>
+    pub fn https_default<A>(authority: A, path: Pr) -> Result<Self, Error>
     where
         Authority: TryFrom<A>,
         <Authority as TryFrom<A>>::Error: Into<HttpError>,
     {
-        let authority = authority.try_into().map_err(Into::into)?;
+        let authority = upstream_authority(authority)?;
         Ok(Self {
             client: client::https_default(),
             scheme: Scheme::HTTPS,
             authority,
             path,
+            config: Default::default(),
         })
     }
 }
@@ -158,17 +199,41 @@ where
     ///
     /// The `path` should implement [`PathRewriter`].
     #[cfg_attr(docsrs, doc(cfg(feature = "nativetls")))]
-    pub fn nativetls_default<A>(authority: A, path: Pr) -> Result<Self, HttpError>
+    pub fn nativetls_default<A>(authority: A, path: Pr) -> Result<Self, Error>
     where
         Authority: TryFrom<A>,
         <Authority as TryFrom<A>>::Error: Into<HttpError>,
     {
-        let authority = authority.try_into().map_err(Into::into)?;
+        let authority = upstream_authority(authority)?;
         Ok(Self {
             client: client::nativetls_default(),
             scheme: Scheme::HTTPS,
             authority,
             path,
+            config: Default::default(),
+        })
+    }
+
+    /// Parses an absolute `uri`, *e.g.* `https://example.com/base`, and uses
+    /// [`client::nativetls_default()`] to build a client. A path in `uri` is prepended to every
+    /// path after the [`PathRewriter`] runs, like
+    /// [`Builder::base_path()`](crate::ReusedServiceBuilder::base_path).
+    ///
+    /// A scheme other than `http` or `https` results in [`Error::UnsupportedScheme`].
+    #[cfg_attr(docsrs, doc(cfg(feature = "nativetls")))]
+    pub fn from_uri<U>(uri: U, path: Pr) -> Result<Self, Error>
+    where
+        Uri: TryFrom<U>,
+        <Uri as TryFrom<U>>::Error: Into<HttpError>,
+    {
+        let uri = Uri::try_from(uri).map_err(|e| Error::InvalidUri(e.into()))?;
+        let (scheme, authority, config) = Config::from_uri(uri, &[Scheme::HTTP, Scheme::HTTPS])?;
+        Ok(Self {
+            client: client::nativetls_default(),
+            scheme,
+            authority,
+            path,
+            config: Arc::new(config),
         })
     }
 }
@@ -185,17 +250,41 @@ where
     ///
     /// The `path` should implement [`PathRewriter`].
     #[cfg_attr(docsrs, doc(cfg(feature = "rustls")))]
-    pub fn https_default<A>(authority: A, path: Pr) -> Result<Self, HttpError>
+    pub fn https_default<A>(authority: A, path: Pr) -> Result<Self, Error>
     where
         Authority: TryFrom<A>,
         <Authority as TryFrom<A>>::Error: Into<HttpError>,
     {
-        let authority = authority.try_into().map_err(Into::into)?;
+        let authority = upstream_authority(authority)?;
         Ok(Self {
             client: client::rustls_default(),
             scheme: Scheme::HTTPS,
             authority,
             path,
+            config: Default::default(),
+        })
+    }
+
+    /// Parses an absolute `uri`, *e.g.* `https://example.com/base`, and uses
+    /// [`client::rustls_default()`] to build a client. A path in `uri` is prepended to every path
+    /// after the [`PathRewriter`] runs, like
+    /// [`Builder::base_path()`](crate::ReusedServiceBuilder::base_path).
+    ///
+    /// A scheme other than `https` results in [`Error::UnsupportedScheme`].
+    #[cfg_attr(docsrs, doc(cfg(feature = "rustls")))]
+    pub fn from_uri<U>(uri: U, path: Pr) -> Result<Self, Error>
+    where
+        Uri: TryFrom<U>,
+        <Uri as TryFrom<U>>::Error: Into<HttpError>,
+    {
+        let uri = Uri::try_from(uri).map_err(|e| Error::InvalidUri(e.into()))?;
+        let (scheme, authority, config) = Config::from_uri(uri, &[Scheme::HTTPS])?;
+        Ok(Self {
+            client: client::rustls_default(),
+            scheme,
+            authority,
+            path,
+            config: Arc::new(config),
         })
     }
 }
@@ -223,15 +312,52 @@ where
             &self.scheme,
             &self.authority,
             &mut self.path,
+            &self.config,
+            None,
         )
     }
 }
 
+impl<C, B, Pr> OneshotService<Pr, C, B>
+where
+    C: Connect + Clone + Send + Sync + 'static,
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<BoxErr>,
+    Pr: PathRewriter,
+{
+    /// Sends a request and returns the response, consuming the service.
+    ///
+    /// ```
+    /// # async fn run_test() {
+    /// # use reverse_proxy_service::OneshotService;
+    /// # use reverse_proxy_service::Static;
+    /// # use hyper::body::Body;
+    /// # use http::Request;
+    /// let svc = OneshotService::http_default("example.com:1234", Static("bar")).unwrap();
+    /// let req = Request::builder()
+    ///     .uri("https://myserver.com/foo")
+    ///     .body(Body::empty())
+    ///     .unwrap();
+    /// // http://example.com:1234/bar
+    /// let _res = svc.oneshot(req).await.unwrap();
+    /// # }
+    /// ```
+    pub async fn oneshot(mut self, req: Request<B>) -> Result<Response<Body>, Error> {
+        match self.call(req).await {
+            Ok(res) => res,
+            Err(e) => match e {},
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
     use crate::test_helper;
-    use crate::ReplaceAll;
+    use crate::{Identity, ReplaceAll};
+
+    use http::StatusCode;
 
     use http::uri::{Parts, Uri};
 
@@ -254,6 +380,19 @@ mod test {
         svc.unwrap()
     }
 
+    #[test]
+    fn debug() {
+        let svc = make_svc();
+        let authority = Uri::try_from(&mockito::server_url()).unwrap();
+        let authority = authority.authority().unwrap().clone();
+        assert_eq!(
+            format!("{svc:?}"),
+            format!(
+                r#"OneshotService {{ scheme: "http", authority: {authority}, path: ReplaceAll("foo", "goo"), .. }}"#
+            )
+        );
+    }
+
     #[tokio::test]
     async fn match_path() {
         let mut svc = make_svc();
@@ -277,4 +416,66 @@ mod test {
         let mut svc = make_svc();
         test_helper::match_header(&mut svc).await;
     }
+
+    #[tokio::test]
+    async fn oneshot() {
+        let _mk = mockito::mock("GET", "/goo").with_body("ok").create();
+
+        let svc = make_svc();
+        let req = Request::builder()
+            .uri("https://test.com/foo")
+            .body(String::new())
+            .unwrap();
+        let res = svc.oneshot(req).await;
+        assert!(res.is_ok());
+        let res = hyper::body::to_bytes(res.unwrap().into_body()).await;
+        assert!(res.is_ok());
+        assert_eq!(res.unwrap(), "ok");
+    }
+
+    #[tokio::test]
+    async fn from_uri() {
+        let _mk = mockito::mock("GET", "/base/goo").with_body("ok").create();
+
+        let url = format!("{}/base/", mockito::server_url());
+        let svc = OneshotService::<_, HttpConnector, String>::from_uri(url.as_str(), Identity);
+        assert!(svc.is_ok());
+        let req = Request::builder()
+            .uri("https://test.com/goo")
+            .body(String::new())
+            .unwrap();
+        let res = svc.unwrap().oneshot(req).await;
+        assert!(res.is_ok());
+        assert_eq!(res.unwrap().status(), StatusCode::OK);
+
+        let svc =
+            OneshotService::<_, HttpConnector, String>::from_uri("https://example.com", Identity);
+        assert!(
+            matches!(svc, Err(Error::UnsupportedScheme(Some(scheme))) if scheme == Scheme::HTTPS)
+        );
+        let svc = OneshotService::<_, HttpConnector, String>::from_uri("example.com", Identity);
+        assert!(matches!(svc, Err(Error::UnsupportedScheme(None))));
+        let svc =
+            OneshotService::<_, HttpConnector, String>::from_uri("ftp://example.com", Identity);
+        assert!(matches!(svc, Err(Error::UnsupportedScheme(Some(_)))));
+        let svc = OneshotService::<_, HttpConnector, String>::from_uri(
+            "http://user@example.com",
+            Identity,
+        );
+        assert!(matches!(svc, Err(Error::InvalidAuthority(_))));
+    }
+
+    #[cfg(feature = "nativetls")]
+    #[test]
+    fn from_uri_https() {
+        let svc = OneshotService::<_, NativeTlsConnector<HttpConnector>, String>::from_uri(
+            "https://example.com/base",
+            Identity,
+        );
+        assert!(svc.is_ok());
+        let svc = svc.unwrap();
+        assert_eq!(svc.scheme, Scheme::HTTPS);
+        assert_eq!(svc.authority, "example.com");
+        assert_eq!(svc.config.base_path.as_deref(), Some("/base"));
+    }
 }
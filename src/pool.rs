@@ -0,0 +1,158 @@
+use http::uri::Uri;
+
+use hyper::client::connect::{Connected, Connection};
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use tower_service::Service;
+
+use std::future::Future;
+use std::io::{self, IoSlice};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+/// Counts of the connections opened by a [`CountingConnector`], shared by its clones.
+#[derive(Debug, Clone, Default)]
+pub struct PoolStats(Arc<Counts>);
+
+#[derive(Debug, Default)]
+struct Counts {
+    connects: AtomicU64,
+    open: AtomicUsize,
+}
+
+impl PoolStats {
+    /// The number of the connections opened so far. If this grows with every request to the same
+    /// host, the connections are not reused.
+    pub fn connects(&self) -> u64 {
+        self.0.connects.load(Ordering::Relaxed)
+    }
+
+    /// The number of the connections currently open, either in use or idle in the pool.
+    pub fn open(&self) -> usize {
+        self.0.open.load(Ordering::Relaxed)
+    }
+}
+
+/// Connector counting the connections opened by `inner`, since hyper's `Client` does not expose
+/// the state of its pool.
+///
+/// ```
+/// use reverse_proxy_service::client::{self, CountingConnector, HttpConnector};
+///
+/// let conn = CountingConnector::new(HttpConnector::new());
+/// let stats = conn.stats().clone();
+/// let svc_builder = reverse_proxy_service::builder(
+///     client::with_connector_default(conn),
+///     http::uri::Scheme::HTTP,
+///     "example.com",
+/// )
+/// .unwrap()
+/// .pool_stats(stats);
+/// # let _svc: reverse_proxy_service::ReusedService<_, _, hyper::Body> =
+/// #     svc_builder.build(reverse_proxy_service::Identity);
+/// ```
+#[derive(Debug, Clone)]
+pub struct CountingConnector<C> {
+    inner: C,
+    stats: PoolStats,
+}
+
+impl<C> CountingConnector<C> {
+    pub fn new(inner: C) -> Self {
+        Self {
+            inner,
+            stats: PoolStats::default(),
+        }
+    }
+
+    pub fn stats(&self) -> &PoolStats {
+        &self.stats
+    }
+}
+
+impl<C> Service<Uri> for CountingConnector<C>
+where
+    C: Service<Uri>,
+    C::Future: Send + 'static,
+{
+    type Response = CountedStream<C::Response>;
+    type Error = C::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, C::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, dst: Uri) -> Self::Future {
+        let stats = self.stats.clone();
+        let connecting = self.inner.call(dst);
+        Box::pin(async move {
+            let inner = connecting.await?;
+            stats.0.connects.fetch_add(1, Ordering::Relaxed);
+            stats.0.open.fetch_add(1, Ordering::Relaxed);
+            Ok(CountedStream { inner, stats })
+        })
+    }
+}
+
+/// Connection opened by a [`CountingConnector`], counted as open until dropped.
+#[derive(Debug)]
+pub struct CountedStream<T> {
+    inner: T,
+    stats: PoolStats,
+}
+
+impl<T> Drop for CountedStream<T> {
+    fn drop(&mut self) {
+        self.stats.0.open.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl<T: Connection> Connection for CountedStream<T> {
+    fn connected(&self) -> Connected {
+        self.inner.connected()
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for CountedStream<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for CountedStream<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
@@ -0,0 +1,247 @@
+use http::uri::Uri;
+
+use hyper::client::connect::HttpConnector;
+
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+
+use tower_service::Service;
+
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+type BoxErr = Box<dyn std::error::Error + Send + Sync>;
+
+tokio::task_local! {
+    static CLIENT_ADDR: Option<SocketAddr>;
+}
+
+/// Version of the [PROXY protocol](https://www.haproxy.org/download/2.8/doc/proxy-protocol.txt).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProxyProtocol {
+    /// Human-readable header, *e.g.* `PROXY TCP4 192.0.2.1 192.0.2.2 56324 443\r\n`.
+    V1,
+    /// Binary header.
+    V2,
+}
+
+impl ProxyProtocol {
+    /// `source` is `None` if the client address is unknown.
+    fn header(self, source: Option<SocketAddr>, destination: SocketAddr) -> Vec<u8> {
+        let addrs = source.map(|source| same_family(source, destination));
+        match self {
+            Self::V1 => match addrs {
+                Some((source, destination)) => format!(
+                    "PROXY {} {} {} {} {}\r\n",
+                    if source.is_ipv4() { "TCP4" } else { "TCP6" },
+                    source.ip(),
+                    destination.ip(),
+                    source.port(),
+                    destination.port(),
+                )
+                .into_bytes(),
+                None => b"PROXY UNKNOWN\r\n".to_vec(),
+            },
+            Self::V2 => {
+                let mut header = b"\r\n\r\n\0\r\nQUIT\n".to_vec();
+                match addrs {
+                    Some((source, destination)) => {
+                        // PROXY command, over TCP
+                        header.push(0x21);
+                        let (family, len) = match source.ip() {
+                            IpAddr::V4(_) => (0x11, 12u16),
+                            IpAddr::V6(_) => (0x21, 36u16),
+                        };
+                        header.push(family);
+                        header.extend_from_slice(&len.to_be_bytes());
+                        for addr in [source, destination] {
+                            match addr.ip() {
+                                IpAddr::V4(ip) => header.extend_from_slice(&ip.octets()),
+                                IpAddr::V6(ip) => header.extend_from_slice(&ip.octets()),
+                            }
+                        }
+                        header.extend_from_slice(&source.port().to_be_bytes());
+                        header.extend_from_slice(&destination.port().to_be_bytes());
+                    }
+                    // LOCAL command, with no addresses
+                    None => header.extend_from_slice(&[0x20, 0x00, 0, 0]),
+                }
+                header
+            }
+        }
+    }
+}
+
+/// Maps an IPv4 address to IPv6 when the other one is IPv6.
+fn same_family(source: SocketAddr, destination: SocketAddr) -> (SocketAddr, SocketAddr) {
+    fn to_v6(addr: SocketAddr) -> SocketAddr {
+        match addr.ip() {
+            IpAddr::V4(ip) => SocketAddr::new(ip.to_ipv6_mapped().into(), addr.port()),
+            IpAddr::V6(_) => addr,
+        }
+    }
+    if source.is_ipv4() == destination.is_ipv4() {
+        (source, destination)
+    } else {
+        (to_v6(source), to_v6(destination))
+    }
+}
+
+/// Connector writing a PROXY protocol header on each new connection, before anything else.
+///
+/// The source address is the one of the client of the request that opens the connection, taken
+/// from an extension of the incoming request: either [`SocketAddr`] or, with the `axum` feature,
+/// `ConnectInfo<SocketAddr>`. Without them the header says the source is unknown (`UNKNOWN` in v1,
+/// `LOCAL` in v2).
+///
+/// Since a pooled connection would carry the header of another client, the client must not reuse
+/// idle connections; [`http_proxy_protocol()`](crate::client::http_proxy_protocol) is configured
+/// so. HTTP/2 multiplexes the requests over one connection, and so should not be used.
+///
+/// To talk TLS to the upstream, wrap this in an `HttpsConnector` after calling
+/// [`enforce_http(false)`](HttpConnector::enforce_http) on the inner connector.
+#[derive(Debug, Clone)]
+pub struct ProxyProtocolConnector {
+    inner: HttpConnector,
+    version: ProxyProtocol,
+}
+
+impl ProxyProtocolConnector {
+    pub fn new(inner: HttpConnector, version: ProxyProtocol) -> Self {
+        Self { inner, version }
+    }
+}
+
+impl Service<Uri> for ProxyProtocolConnector {
+    type Response = TcpStream;
+    type Error = BoxErr;
+    type Future = Pin<Box<dyn Future<Output = Result<TcpStream, BoxErr>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, dst: Uri) -> Self::Future {
+        let source = CLIENT_ADDR.try_with(|addr| *addr).ok().flatten();
+        let version = self.version;
+        let connecting = self.inner.call(dst);
+        Box::pin(async move {
+            let mut stream = connecting.await?;
+            let header = version.header(source, stream.peer_addr()?);
+            stream.write_all(&header).await?;
+            Ok(stream)
+        })
+    }
+}
+
+/// Makes `addr` visible to [`ProxyProtocolConnector`]s connecting while `f` is polled.
+pub(crate) async fn scope<F: Future>(addr: Option<SocketAddr>, f: F) -> F::Output {
+    CLIENT_ADDR.scope(addr, f).await
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{client, Identity, ReusedService};
+
+    use http::{Request, StatusCode};
+
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+
+    use std::sync::Arc;
+
+    #[test]
+    fn header_v1() {
+        let source = "192.0.2.1:56324".parse().unwrap();
+        let destination = "192.0.2.2:443".parse().unwrap();
+        assert_eq!(
+            ProxyProtocol::V1.header(Some(source), destination),
+            b"PROXY TCP4 192.0.2.1 192.0.2.2 56324 443\r\n"
+        );
+        assert_eq!(
+            ProxyProtocol::V1.header(Some(source), "[::1]:443".parse().unwrap()),
+            b"PROXY TCP6 ::ffff:192.0.2.1 ::1 56324 443\r\n"
+        );
+        assert_eq!(
+            ProxyProtocol::V1.header(None, destination),
+            b"PROXY UNKNOWN\r\n"
+        );
+    }
+
+    #[test]
+    fn header_v2() {
+        let source = "192.0.2.1:56324".parse().unwrap();
+        let destination = "192.0.2.2:443".parse().unwrap();
+        let header = ProxyProtocol::V2.header(Some(source), destination);
+        assert_eq!(&header[..12], b"\r\n\r\n\0\r\nQUIT\n");
+        assert_eq!(
+            &header[12..],
+            [0x21, 0x11, 0, 12, 192, 0, 2, 1, 192, 0, 2, 2, 0xdc, 0x04, 0x01, 0xbb]
+        );
+
+        let header = ProxyProtocol::V2.header(None, destination);
+        assert_eq!(&header[12..], [0x20, 0x00, 0, 0]);
+    }
+
+    #[tokio::test]
+    async fn connector() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let upstream = listener.local_addr().unwrap();
+        let expected = format!(
+            "PROXY TCP4 192.0.2.1 127.0.0.1 56324 {}\r\n",
+            upstream.port()
+        );
+
+        // Accepts a connection per request, and responds 200 only if the PROXY line is expected.
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let expected = expected.clone();
+                tokio::spawn(async move {
+                    let mut buf = Vec::new();
+                    while !buf.ends_with(b"\r\n\r\n") {
+                        let mut byte = [0; 1];
+                        if stream.read_exact(&mut byte).await.is_err() {
+                            return;
+                        }
+                        buf.push(byte[0]);
+                    }
+                    let status = if buf.starts_with(expected.as_bytes()) {
+                        200
+                    } else {
+                        400
+                    };
+                    let res = format!("HTTP/1.1 {status} OK\r\ncontent-length: 0\r\n\r\n");
+                    let _ = stream.write_all(res.as_bytes()).await;
+                });
+            }
+        });
+
+        let client = Arc::new(client::http_proxy_protocol(ProxyProtocol::V1));
+        let svc = ReusedService::from(client, "http", upstream.to_string(), Identity);
+        assert!(svc.is_ok());
+        let mut svc = svc.unwrap();
+
+        for _ in 0..2 {
+            let mut req = Request::builder()
+                .uri("https://test.com/foo")
+                .body(String::new())
+                .unwrap();
+            req.extensions_mut()
+                .insert::<SocketAddr>("192.0.2.1:56324".parse().unwrap());
+            let res = svc.call(req).await.unwrap();
+            assert!(res.is_ok());
+            assert_eq!(res.unwrap().status(), StatusCode::OK);
+        }
+
+        let req = Request::builder()
+            .uri("https://test.com/foo")
+            .body(String::new())
+            .unwrap();
+        let res = svc.call(req).await.unwrap();
+        assert!(res.is_ok());
+        assert_eq!(res.unwrap().status(), StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,146 @@
+use bytes::{Bytes, BytesMut};
+
+use http::HeaderMap;
+
+use hyper::body::{HttpBody, SizeHint};
+
+use tokio::io::{AsyncRead, ReadBuf};
+
+use std::fmt;
+use std::io;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+/// The size of a chunk read at once.
+const CHUNK_SIZE: usize = 8 * 1024;
+
+/// A body streamed from an [`AsyncRead`], *e.g.* a file or a socket, without loading it into
+/// memory.
+///
+/// ```
+/// # use reverse_proxy_service::AsyncReadBody;
+/// # async fn run() -> Result<(), reverse_proxy_service::Error> {
+/// let mut svc = reverse_proxy_service::builder_http("example.com")
+///     .unwrap()
+///     .build(reverse_proxy_service::Identity);
+///
+/// let upload = std::io::Cursor::new(b"large upload".to_vec());
+/// let req = http::Request::post("/upload")
+///     .body(AsyncReadBody::new(upload))
+///     .unwrap();
+/// let res = svc.ready_and_call(req).await?;
+/// println!("{}", res.status());
+/// # Ok(())
+/// # }
+/// ```
+pub struct AsyncReadBody<R> {
+    reader: Option<Pin<Box<R>>>,
+    buf: BytesMut,
+    len: Option<u64>,
+}
+
+impl<R: AsyncRead> AsyncReadBody<R> {
+    /// Streams the body from `reader` until EOF.
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader: Some(Box::pin(reader)),
+            buf: BytesMut::new(),
+            len: None,
+        }
+    }
+
+    /// Same as [`new()`](Self::new), but tells the length of the body, so that the request is
+    /// sent with `Content-Length` instead of chunked.
+    ///
+    /// The body must be exactly `len` bytes long.
+    pub fn with_len(reader: R, len: u64) -> Self {
+        Self {
+            len: Some(len),
+            ..Self::new(reader)
+        }
+    }
+}
+
+impl<R> fmt::Debug for AsyncReadBody<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AsyncReadBody")
+            .field("len", &self.len)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<R: AsyncRead> HttpBody for AsyncReadBody<R> {
+    type Data = Bytes;
+    type Error = io::Error;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let this = &mut *self;
+        let Some(reader) = this.reader.as_mut() else {
+            return Poll::Ready(None);
+        };
+        this.buf.resize(CHUNK_SIZE, 0);
+        let mut read = ReadBuf::new(&mut this.buf);
+        let res = ready!(reader.as_mut().poll_read(cx, &mut read));
+        let filled = read.filled().len();
+        match res {
+            Ok(()) if filled > 0 => {
+                this.buf.truncate(filled);
+                Poll::Ready(Some(Ok(this.buf.split().freeze())))
+            }
+            Ok(()) => {
+                this.reader = None;
+                Poll::Ready(None)
+            }
+            Err(err) => {
+                this.reader = None;
+                Poll::Ready(Some(Err(err)))
+            }
+        }
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        Poll::Ready(Ok(None))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.reader.is_none() || self.len == Some(0)
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match self.len {
+            Some(len) => SizeHint::with_exact(len),
+            None => SizeHint::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn chunks() {
+        let data = (0..3 * CHUNK_SIZE / 2).map(|i| i as u8).collect::<Vec<_>>();
+        let mut body = AsyncReadBody::new(&data[..]);
+        assert!(!body.is_end_stream());
+
+        let first = body.data().await.unwrap().unwrap();
+        assert_eq!(first.len(), CHUNK_SIZE);
+        let second = body.data().await.unwrap().unwrap();
+        assert_eq!(second.len(), CHUNK_SIZE / 2);
+        assert!(body.data().await.is_none());
+        assert!(body.is_end_stream());
+        assert_eq!([first, second].concat(), data);
+
+        let body = AsyncReadBody::with_len(&data[..], data.len() as u64);
+        assert_eq!(body.size_hint().exact(), Some(data.len() as u64));
+        let body = hyper::body::to_bytes(body).await.unwrap();
+        assert_eq!(body, data);
+    }
+}
@@ -0,0 +1,327 @@
+use crate::config::join_path;
+
+use http::header::{self, HeaderMap, HeaderValue};
+use http::uri::{Scheme, Uri};
+use http::{Method, Response, StatusCode};
+
+/// The target of a redirect response, resolved against the URI of the request.
+pub(crate) fn location<B>(res: &Response<B>, base: &Uri) -> Option<Uri> {
+    match res.status() {
+        StatusCode::MOVED_PERMANENTLY
+        | StatusCode::FOUND
+        | StatusCode::SEE_OTHER
+        | StatusCode::TEMPORARY_REDIRECT
+        | StatusCode::PERMANENT_REDIRECT => {}
+        _ => return None,
+    }
+    let location = res.headers().get(header::LOCATION)?.to_str().ok()?;
+    resolve(base, location)
+}
+
+/// Whether the redirected request should be a `GET` without body, as browsers do.
+pub(crate) fn turns_into_get(status: StatusCode, method: &Method) -> bool {
+    match status {
+        StatusCode::SEE_OTHER => method != Method::HEAD,
+        StatusCode::MOVED_PERMANENTLY | StatusCode::FOUND => method == Method::POST,
+        _ => false,
+    }
+}
+
+/// Replaces an absolute `Location` of the same origin as `upstream` with the path, query and
+/// fragment only. The other `Location`s are left as they are.
+pub(crate) fn relativize_location(headers: &mut HeaderMap, upstream: &Uri) {
+    let Some(location) = headers.get(header::LOCATION) else {
+        return;
+    };
+    let Some(relative) = location.to_str().ok().and_then(|l| relative(l, upstream)) else {
+        return;
+    };
+    if let Ok(relative) = HeaderValue::try_from(relative) {
+        headers.insert(header::LOCATION, relative);
+    }
+}
+
+/// Replaces a `Location` pointing to `upstream`, either absolute or relative to the root, with
+/// the URL under `public`. The `base_path` of the upstream is cut out of the path, and the path of
+/// `public` is prepended instead.
+///
+/// The other `Location`s, including the ones outside `base_path`, are left as they are.
+pub(crate) fn publicize_location(
+    headers: &mut HeaderMap,
+    upstream: &Uri,
+    public: &Uri,
+    base_path: Option<&str>,
+) {
+    let Some(location) = headers.get(header::LOCATION) else {
+        return;
+    };
+    let Some(public) = location
+        .to_str()
+        .ok()
+        .and_then(|l| public_location(l, upstream, public, base_path))
+    else {
+        return;
+    };
+    if let Ok(public) = HeaderValue::try_from(public) {
+        headers.insert(header::LOCATION, public);
+    }
+}
+
+fn public_location(
+    location: &str,
+    upstream: &Uri,
+    public: &Uri,
+    base_path: Option<&str>,
+) -> Option<String> {
+    let relative = if location.starts_with('/') && !location.starts_with("//") {
+        location.to_owned()
+    } else {
+        relative(location, upstream)?
+    };
+    let (path, rest) = relative.split_at(relative.find(['?', '#']).unwrap_or(relative.len()));
+    let path = match base_path.map(|base| base.trim_matches('/')) {
+        Some(base) if !base.is_empty() => match path[1..].strip_prefix(base)? {
+            "" => "/",
+            path if path.starts_with('/') => path,
+            _ => return None,
+        },
+        _ => path,
+    };
+    let (scheme, authority) = (public.scheme()?, public.authority()?);
+    let path = join_path(public.path(), path);
+    Some(format!("{scheme}://{authority}{path}{rest}"))
+}
+
+/// Replaces the origin of `Origin` and `Referer` with the one of `upstream`, if it is the host
+/// `public` addressed by the client. The path and query of `Referer` are kept, and the headers of
+/// another origin are left as they are.
+pub(crate) fn rewrite_origin(headers: &mut HeaderMap, public: &str, upstream: &Uri) {
+    let (Some(scheme), Some(authority)) = (upstream.scheme(), upstream.authority()) else {
+        return;
+    };
+    for name in [header::ORIGIN, header::REFERER] {
+        let Some(uri) = headers
+            .get(&name)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<Uri>().ok())
+        else {
+            continue;
+        };
+        if !uri
+            .authority()
+            .is_some_and(|a| a.as_str().eq_ignore_ascii_case(public))
+        {
+            continue;
+        }
+        let rest = match &name {
+            &header::REFERER => uri.path_and_query().map_or("/", |p_and_q| p_and_q.as_str()),
+            _ => "",
+        };
+        if let Ok(value) = HeaderValue::try_from(format!("{scheme}://{authority}{rest}")) {
+            headers.insert(name, value);
+        }
+    }
+}
+
+fn relative(location: &str, upstream: &Uri) -> Option<String> {
+    let uri = location.parse::<Uri>().ok()?;
+    if !same_origin(&uri, upstream) {
+        return None;
+    }
+    // `Uri` drops the fragment, so that the rest is cut out of the original string.
+    let (_, rest) = location.split_once("://")?;
+    let rest = &rest[rest.find(['/', '?', '#']).unwrap_or(rest.len())..];
+    Some(if rest.starts_with('/') {
+        rest.to_owned()
+    } else {
+        format!("/{rest}")
+    })
+}
+
+fn same_origin(a: &Uri, b: &Uri) -> bool {
+    fn port(uri: &Uri) -> Option<u16> {
+        uri.port_u16().or(match uri.scheme() {
+            Some(s) if *s == Scheme::HTTP => Some(80),
+            Some(s) if *s == Scheme::HTTPS => Some(443),
+            _ => None,
+        })
+    }
+
+    match (a.scheme(), a.host(), b.scheme(), b.host()) {
+        (Some(sa), Some(ha), Some(sb), Some(hb)) => {
+            sa == sb && ha.eq_ignore_ascii_case(hb) && port(a) == port(b)
+        }
+        _ => false,
+    }
+}
+
+fn resolve(base: &Uri, location: &str) -> Option<Uri> {
+    if let Ok(uri) = location.parse::<Uri>() {
+        if uri.scheme().is_some() && uri.authority().is_some() {
+            return Some(uri);
+        }
+    }
+
+    let scheme = base.scheme()?;
+    if let Some(rest) = location.strip_prefix("//") {
+        return format!("{scheme}://{rest}").parse().ok();
+    }
+
+    let path = base.path();
+    let p_and_q = if location.starts_with('/') {
+        location.to_owned()
+    } else if location.starts_with('?') {
+        format!("{path}{location}")
+    } else {
+        let dir = &path[..path.rfind('/').map_or(0, |i| i + 1)];
+        format!("{dir}{location}")
+    };
+    Uri::builder()
+        .scheme(scheme.clone())
+        .authority(base.authority()?.clone())
+        .path_and_query(p_and_q)
+        .build()
+        .ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn resolve_location() {
+        let base = Uri::from_static("http://example.com/foo/bar?key=value");
+        let resolve = |location| resolve(&base, location).map(|uri| uri.to_string());
+
+        assert_eq!(
+            resolve("https://example.net/baz").as_deref(),
+            Some("https://example.net/baz")
+        );
+        assert_eq!(
+            resolve("//example.net/baz").as_deref(),
+            Some("http://example.net/baz")
+        );
+        assert_eq!(
+            resolve("/baz?k=v").as_deref(),
+            Some("http://example.com/baz?k=v")
+        );
+        assert_eq!(
+            resolve("baz").as_deref(),
+            Some("http://example.com/foo/baz")
+        );
+        assert_eq!(
+            resolve("?k=v").as_deref(),
+            Some("http://example.com/foo/bar?k=v")
+        );
+    }
+
+    #[test]
+    fn relative_location() {
+        let upstream = Uri::from_static("http://internal:8080/foo");
+        let relative = |location| relative(location, &upstream);
+
+        assert_eq!(
+            relative("http://internal:8080/bar?k=v#top").as_deref(),
+            Some("/bar?k=v#top")
+        );
+        assert_eq!(relative("HTTP://Internal:8080").as_deref(), Some("/"));
+        assert_eq!(
+            relative("http://internal:8080?k=v").as_deref(),
+            Some("/?k=v")
+        );
+        assert_eq!(relative("/bar"), None);
+        assert_eq!(relative("https://internal:8080/bar"), None);
+        assert_eq!(relative("http://internal/bar"), None);
+        assert_eq!(relative("http://example.com:8080/bar"), None);
+
+        let upstream = Uri::from_static("https://internal/foo");
+        assert_eq!(
+            super::relative("https://internal:443/bar", &upstream).as_deref(),
+            Some("/bar")
+        );
+    }
+
+    #[test]
+    fn public_location() {
+        let upstream = Uri::from_static("http://internal:8080/service-a/foo");
+        let public = Uri::from_static("https://example.com/api");
+        let public_location =
+            |location, base_path| super::public_location(location, &upstream, &public, base_path);
+
+        assert_eq!(
+            public_location("http://internal:8080/bar?k=v#top", None).as_deref(),
+            Some("https://example.com/api/bar?k=v#top")
+        );
+        assert_eq!(
+            public_location("/bar", None).as_deref(),
+            Some("https://example.com/api/bar")
+        );
+        assert_eq!(
+            public_location("/service-a/bar?k=v", Some("/service-a/")).as_deref(),
+            Some("https://example.com/api/bar?k=v")
+        );
+        assert_eq!(
+            public_location("http://internal:8080/service-a", Some("/service-a")).as_deref(),
+            Some("https://example.com/api/")
+        );
+        assert_eq!(public_location("/service-ab/bar", Some("/service-a")), None);
+        assert_eq!(public_location("/bar", Some("/service-a")), None);
+        assert_eq!(public_location("https://example.net/bar", None), None);
+        assert_eq!(public_location("//internal:8080/bar", None), None);
+        assert_eq!(public_location("bar", None), None);
+
+        let public = Uri::from_static("https://example.com");
+        assert_eq!(
+            super::public_location("/bar", &upstream, &public, None).as_deref(),
+            Some("https://example.com/bar")
+        );
+    }
+
+    #[test]
+    fn origin() {
+        let upstream = Uri::from_static("http://internal:8080/foo");
+        let rewrite = |origin: &str, referer: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::ORIGIN, origin.parse().unwrap());
+            headers.insert(header::REFERER, referer.parse().unwrap());
+            rewrite_origin(&mut headers, "example.com", &upstream);
+            [header::ORIGIN, header::REFERER].map(|name| headers[name].to_str().unwrap().to_owned())
+        };
+
+        assert_eq!(
+            rewrite("https://example.com", "https://example.com/page?k=v"),
+            ["http://internal:8080", "http://internal:8080/page?k=v"]
+        );
+        assert_eq!(
+            rewrite("https://Example.com", "https://example.com"),
+            ["http://internal:8080", "http://internal:8080/"]
+        );
+        // Another origin, or an opaque one.
+        assert_eq!(
+            rewrite("https://example.net", "https://example.com:8443/page"),
+            ["https://example.net", "https://example.com:8443/page"]
+        );
+        assert_eq!(rewrite("null", "/page"), ["null", "/page"]);
+
+        let mut headers = HeaderMap::new();
+        rewrite_origin(&mut headers, "example.com", &upstream);
+        assert!(headers.is_empty());
+    }
+
+    #[test]
+    fn method_change() {
+        assert!(turns_into_get(StatusCode::SEE_OTHER, &Method::POST));
+        assert!(turns_into_get(StatusCode::SEE_OTHER, &Method::PUT));
+        assert!(!turns_into_get(StatusCode::SEE_OTHER, &Method::HEAD));
+        assert!(turns_into_get(StatusCode::FOUND, &Method::POST));
+        assert!(!turns_into_get(StatusCode::FOUND, &Method::PUT));
+        assert!(!turns_into_get(
+            StatusCode::TEMPORARY_REDIRECT,
+            &Method::POST
+        ));
+        assert!(!turns_into_get(
+            StatusCode::PERMANENT_REDIRECT,
+            &Method::POST
+        ));
+    }
+}
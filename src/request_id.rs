@@ -0,0 +1,43 @@
+use http::header::{HeaderMap, HeaderName, HeaderValue};
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::SystemTime;
+
+/// Generates correlation IDs, `{seed}-{counter}` in hex.
+///
+/// The seed is random per instance, and the counter increments per ID, so the IDs are unique
+/// without any external crate.
+#[derive(Debug)]
+pub(crate) struct RequestId {
+    header: HeaderName,
+    seed: u64,
+    counter: AtomicU64,
+}
+
+impl RequestId {
+    pub(crate) fn new(header: HeaderName) -> Self {
+        let mut hasher = RandomState::new().build_hasher();
+        if let Ok(now) = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+            hasher.write_u128(now.as_nanos());
+        }
+        Self {
+            header,
+            seed: hasher.finish(),
+            counter: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the header name and the ID of the request, inserting a new ID if absent.
+    pub(crate) fn get_or_insert(&self, headers: &mut HeaderMap) -> (HeaderName, HeaderValue) {
+        if let Some(id) = headers.get(&self.header) {
+            return (self.header.clone(), id.clone());
+        }
+        let counter = self.counter.fetch_add(1, Ordering::Relaxed);
+        let id = format!("{:016x}-{counter:016x}", self.seed);
+        let id = HeaderValue::try_from(id).expect("hex digits are valid header values");
+        headers.insert(self.header.clone(), id.clone());
+        (self.header.clone(), id)
+    }
+}
@@ -0,0 +1,65 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+/// Retries of a request failing to connect.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct Retry {
+    pub(crate) max: usize,
+    pub(crate) backoff: Option<Backoff>,
+}
+
+/// Exponential backoff between the retries, with a random jitter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Backoff {
+    pub(crate) base: Duration,
+    pub(crate) max: Duration,
+    pub(crate) jitter: Duration,
+}
+
+impl Backoff {
+    /// The delay before the retry `attempt`, counted from 0: `base * 2^attempt` up to `max`, plus
+    /// a random duration up to `jitter`.
+    pub(crate) fn delay(&self, attempt: usize) -> Duration {
+        let exp = self
+            .base
+            .checked_mul(1 << attempt.min(31))
+            .map_or(self.max, |delay| delay.min(self.max));
+        exp + self.jitter.mul_f64(random())
+    }
+}
+
+/// A random number in `[0, 1)`, from the random keys of [`RandomState`].
+fn random() -> f64 {
+    let bits = RandomState::new().build_hasher().finish() >> 11;
+    bits as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn delay() {
+        let backoff = Backoff {
+            base: Duration::from_millis(100),
+            max: Duration::from_secs(1),
+            jitter: Duration::ZERO,
+        };
+        assert_eq!(backoff.delay(0), Duration::from_millis(100));
+        assert_eq!(backoff.delay(1), Duration::from_millis(200));
+        assert_eq!(backoff.delay(3), Duration::from_millis(800));
+        assert_eq!(backoff.delay(4), Duration::from_secs(1));
+        assert_eq!(backoff.delay(100), Duration::from_secs(1));
+
+        let backoff = Backoff {
+            jitter: Duration::from_millis(50),
+            ..backoff
+        };
+        for attempt in 0..8 {
+            let delay = backoff.delay(attempt);
+            let exp = Duration::from_millis(100 << attempt).min(Duration::from_secs(1));
+            assert!(exp <= delay && delay <= exp + Duration::from_millis(50));
+        }
+    }
+}
@@ -3,51 +3,140 @@
 //! A "path" does not include a query. See [`http::uri::Uri`].
 
 use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::str::FromStr;
 
+use http::header::{self, HeaderName, HeaderValue};
 use http::uri::{Authority, Scheme, Uri};
 use http::Error as HttpError;
-use http::Request;
+use http::{Method, Request};
 
 use regex::{Regex as LibRegex, Replacer};
 
 /// Represents a rule to rewrite a path `/foo/bar/baz` to new one.
 ///
 /// A "path" does not include a query. See [`http::uri::Uri`].
+///
+/// A tuple of up to 8 rewriters applies them from left to right, *e.g.*
+/// `(TrimPrefix("/api"), AppendPrefix("/v2"))` rewrites `/api/foo` to `/v2/foo`. An intermediate
+/// result borrowed from the `path` is passed on as is, but an owned one cannot outlive the call,
+/// so that the output of the next rewriter is copied into a new `String`.
+///
+/// ```
+/// # use reverse_proxy_service::rewrite::{AppendPrefix, PathRewriter, TrimPrefix};
+/// let mut rw = (TrimPrefix("/api"), AppendPrefix("/v2"));
+/// assert_eq!(rw.rewrite("/api/foo"), "/v2/foo");
+/// ```
 pub trait PathRewriter {
     fn rewrite<'a>(&'a mut self, path: &'a str) -> Cow<'a, str>;
 
+    /// Rewrites the URI of `req` to the one sent to the upstream.
+    ///
+    /// This is not available on a trait object, so that `dyn PathRewriter` only rewrites the path
+    /// by [`rewrite()`](Self::rewrite).
     fn rewrite_uri<B>(
         &mut self,
         req: &mut Request<B>,
         scheme: &Scheme,
         authority: &Authority,
-    ) -> Result<(), HttpError> {
+    ) -> Result<(), HttpError>
+    where
+        Self: Sized,
+    {
         let uri = {
             let uri = req.uri();
             let path = self.rewrite(uri.path());
-            if let Some(query) = uri.query() {
-                let mut p_and_q = path.into_owned();
-                p_and_q.push('?');
-                p_and_q.push_str(query);
-
-                Uri::builder()
-                    .scheme(scheme.clone())
-                    .authority(authority.clone())
-                    .path_and_query(p_and_q)
-                    .build()
-            } else {
-                Uri::builder()
-                    .scheme(scheme.clone())
-                    .authority(authority.clone())
-                    .path_and_query(&*path)
-                    .build()
-            }
+            replace_path(uri, &path, scheme, authority)
         }?;
         *req.uri_mut() = uri;
         Ok(())
     }
 }
 
+/// Represents a rule to rewrite a path, which may need to wait for I/O, *e.g.* looking up an
+/// external mapping.
+///
+/// Every [`PathRewriter`] is an [`AsyncPathRewriter`] resolving immediately. A service is built by
+/// [`Builder::build_async()`](crate::ReusedServiceBuilder::build_async), which clones the rewriter
+/// per request.
+///
+/// ```
+/// # use reverse_proxy_service::rewrite::AsyncPathRewriter;
+/// # use std::borrow::Cow;
+/// # use std::future::Future;
+/// # use std::pin::Pin;
+/// #[derive(Clone)]
+/// struct Lookup;
+///
+/// impl AsyncPathRewriter for Lookup {
+///     fn rewrite_async<'a>(
+///         &'a mut self,
+///         path: &'a str,
+///     ) -> Pin<Box<dyn Future<Output = Cow<'a, str>> + Send + 'a>> {
+///         Box::pin(async move {
+///             // e.g. query a cache here
+///             format!("/mapped{path}").into()
+///         })
+///     }
+/// }
+/// ```
+pub trait AsyncPathRewriter {
+    fn rewrite_async<'a>(
+        &'a mut self,
+        path: &'a str,
+    ) -> Pin<Box<dyn Future<Output = Cow<'a, str>> + Send + 'a>>;
+}
+
+impl<Pr: PathRewriter + Send> AsyncPathRewriter for Pr {
+    #[inline]
+    fn rewrite_async<'a>(
+        &'a mut self,
+        path: &'a str,
+    ) -> Pin<Box<dyn Future<Output = Cow<'a, str>> + Send + 'a>> {
+        Box::pin(std::future::ready(self.rewrite(path)))
+    }
+}
+
+/// Replaces the scheme, authority and path of the `uri`, keeping the query.
+pub(crate) fn replace_path(
+    uri: &Uri,
+    path: &str,
+    scheme: &Scheme,
+    authority: &Authority,
+) -> Result<Uri, HttpError> {
+    // An unchanged path is passed on without parsing it again.
+    if let Some(p_and_q) = uri
+        .path_and_query()
+        .filter(|p_and_q| p_and_q.path() == path)
+    {
+        return Uri::builder()
+            .scheme(scheme.clone())
+            .authority(authority.clone())
+            .path_and_query(p_and_q.clone())
+            .build();
+    }
+    if let Some(query) = uri.query() {
+        let mut p_and_q = path.to_owned();
+        p_and_q.push('?');
+        p_and_q.push_str(query);
+
+        Uri::builder()
+            .scheme(scheme.clone())
+            .authority(authority.clone())
+            .path_and_query(p_and_q)
+            .build()
+    } else {
+        Uri::builder()
+            .scheme(scheme.clone())
+            .authority(authority.clone())
+            .path_and_query(path)
+            .build()
+    }
+}
+
 /// Identity function, that is, this returns the `path` as is.
 ///
 /// ```
@@ -71,6 +160,7 @@ impl PathRewriter for Identity {
 /// assert_eq!(Static("bar").rewrite("foo"), "bar");
 /// ```
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Static<'a>(pub &'a str);
 
 impl PathRewriter for Static<'_> {
@@ -87,6 +177,7 @@ impl PathRewriter for Static<'_> {
 /// assert_eq!(ReplaceAll("foo", "bar").rewrite("foofoo"), "barbar");
 /// ```
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ReplaceAll<'a>(pub &'a str, pub &'a str);
 
 impl PathRewriter for ReplaceAll<'_> {
@@ -107,6 +198,7 @@ impl PathRewriter for ReplaceAll<'_> {
 /// assert_eq!(ReplaceN("foo", "bar", 3).rewrite("foofoo"), "barbar");
 /// ```
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ReplaceN<'a>(pub &'a str, pub &'a str, pub usize);
 
 impl PathRewriter for ReplaceN<'_> {
@@ -127,6 +219,7 @@ impl PathRewriter for ReplaceN<'_> {
 /// assert_eq!(TrimPrefix("bar").rewrite("foobarfoo"), "foobarfoo");
 /// ```
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TrimPrefix<'a>(pub &'a str);
 
 impl PathRewriter for TrimPrefix<'_> {
@@ -139,6 +232,194 @@ impl PathRewriter for TrimPrefix<'_> {
     }
 }
 
+/// Trims the longest of the prefixes that exists, *e.g.* to route several public prefixes to one
+/// upstream.
+///
+/// ```
+/// # use reverse_proxy_service::rewrite::{PathRewriter, TrimPrefixes};
+/// let mut rw = TrimPrefixes(&["/api", "/api/v1", "/public"]);
+/// assert_eq!(rw.rewrite("/api/v1/users"), "/users");
+/// assert_eq!(rw.rewrite("/api/users"), "/users");
+/// assert_eq!(rw.rewrite("/public/users"), "/users");
+/// assert_eq!(rw.rewrite("/private/users"), "/private/users");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TrimPrefixes<'a>(pub &'a [&'a str]);
+
+impl PathRewriter for TrimPrefixes<'_> {
+    fn rewrite<'a>(&mut self, path: &'a str) -> Cow<'a, str> {
+        self.0
+            .iter()
+            .filter_map(|prefix| path.strip_prefix(prefix))
+            .min_by_key(|stripped| stripped.len())
+            .unwrap_or(path)
+            .into()
+    }
+}
+
+/// Trims a prefix if exists, same as [`TrimPrefix`], and records it in the `X-Forwarded-Prefix`
+/// header of the request so that the upstream can build absolute URLs.
+///
+/// The header is set only if the prefix is trimmed, overwriting the one sent by the client.
+/// Otherwise the one sent by the client is removed, so that the client cannot spoof the prefix.
+///
+/// ```
+/// # use reverse_proxy_service::rewrite::{PathRewriter, TrimPrefixForwarded};
+/// # use http::uri::{Authority, Scheme};
+/// # use http::Request;
+/// let mut req = Request::builder().uri("/service-a/foo").body(()).unwrap();
+/// let authority = Authority::from_static("example.com");
+/// TrimPrefixForwarded("/service-a")
+///     .rewrite_uri(&mut req, &Scheme::HTTP, &authority)
+///     .unwrap();
+///
+/// assert_eq!(req.uri(), "http://example.com/foo");
+/// assert_eq!(req.headers()["x-forwarded-prefix"], "/service-a");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TrimPrefixForwarded<'a>(pub &'a str);
+
+impl PathRewriter for TrimPrefixForwarded<'_> {
+    fn rewrite<'a>(&mut self, path: &'a str) -> Cow<'a, str> {
+        path.strip_prefix(self.0).unwrap_or(path).into()
+    }
+
+    fn rewrite_uri<B>(
+        &mut self,
+        req: &mut Request<B>,
+        scheme: &Scheme,
+        authority: &Authority,
+    ) -> Result<(), HttpError> {
+        let trimmed = req.uri().path().starts_with(self.0);
+        let uri = {
+            let uri = req.uri();
+            let path = self.rewrite(uri.path());
+            replace_path(uri, &path, scheme, authority)
+        }?;
+        *req.uri_mut() = uri;
+        if trimmed {
+            let prefix = HeaderValue::from_str(self.0)?;
+            req.headers_mut().insert(X_FORWARDED_PREFIX, prefix);
+        } else {
+            req.headers_mut().remove(X_FORWARDED_PREFIX);
+        }
+        Ok(())
+    }
+}
+
+const X_FORWARDED_PREFIX: HeaderName = HeaderName::from_static("x-forwarded-prefix");
+
+/// `RewriteHost(f, rw)` sends a request to the host returned by `f`, *e.g.* the tenant in
+/// `/t/acme/foo`, and rewrites the path by `rw`.
+///
+/// `f` receives the path as sent by the client, and returns the host (optionally with a port) or
+/// `None` to keep the authority of the service. The authority of the URI and the `Host` header are
+/// both replaced with the host. An invalid host results in [`Error::InvalidUri`](crate::Error).
+///
+/// ```
+/// # use reverse_proxy_service::rewrite::{PathRewriter, RegexAll, RewriteHost};
+/// # use http::uri::{Authority, Scheme};
+/// # use http::Request;
+/// # use regex::Regex;
+/// let tenant = |path: &str| {
+///     let tenant = path.strip_prefix("/t/")?.split('/').next()?;
+///     Some(format!("{tenant}.internal"))
+/// };
+/// let mut rw = RewriteHost(tenant, RegexAll(Regex::new("^/t/[^/]+").unwrap(), ""));
+///
+/// let mut req = Request::builder().uri("/t/acme/foo").body(()).unwrap();
+/// let authority = Authority::from_static("example.com");
+/// rw.rewrite_uri(&mut req, &Scheme::HTTP, &authority).unwrap();
+/// assert_eq!(req.uri(), "http://acme.internal/foo");
+/// assert_eq!(req.headers()["host"], "acme.internal");
+///
+/// let mut req = Request::builder().uri("/foo").body(()).unwrap();
+/// rw.rewrite_uri(&mut req, &Scheme::HTTP, &authority).unwrap();
+/// assert_eq!(req.uri(), "http://example.com/foo");
+/// assert!(!req.headers().contains_key("host"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct RewriteHost<F, Pr>(pub F, pub Pr);
+
+impl<F, Pr> PathRewriter for RewriteHost<F, Pr>
+where
+    F: FnMut(&str) -> Option<String>,
+    Pr: PathRewriter,
+{
+    fn rewrite<'a>(&'a mut self, path: &'a str) -> Cow<'a, str> {
+        self.1.rewrite(path)
+    }
+
+    fn rewrite_uri<B>(
+        &mut self,
+        req: &mut Request<B>,
+        scheme: &Scheme,
+        authority: &Authority,
+    ) -> Result<(), HttpError> {
+        let host = (self.0)(req.uri().path())
+            .map(Authority::try_from)
+            .transpose()?;
+        self.1
+            .rewrite_uri(req, scheme, host.as_ref().unwrap_or(authority))?;
+        if let Some(host) = host {
+            let host = HeaderValue::from_str(host.as_str())?;
+            req.headers_mut().insert(header::HOST, host);
+        }
+        Ok(())
+    }
+}
+
+/// `Deny(f, rw)` rejects a request with [`Error::Forbidden`](crate::Error) if `f` returns `true`
+/// for the path as sent by the client, *e.g.* `/internal/...`, without contacting the upstream.
+/// Otherwise the path is rewritten by `rw`.
+///
+/// The rejection is made in [`rewrite_uri()`](PathRewriter::rewrite_uri), so that `Deny` should be
+/// the outermost rewriter of a service built by
+/// [`Builder::build()`](crate::ReusedServiceBuilder::build). [`rewrite()`](PathRewriter::rewrite)
+/// only rewrites by `rw`.
+///
+/// ```
+/// # use reverse_proxy_service::rewrite::{Deny, PathRewriter, TrimPrefix};
+/// # use http::uri::{Authority, Scheme};
+/// # use http::Request;
+/// let mut rw = Deny(|path: &str| path.starts_with("/api/internal/"), TrimPrefix("/api"));
+/// let authority = Authority::from_static("example.com");
+///
+/// let mut req = Request::builder().uri("/api/users").body(()).unwrap();
+/// rw.rewrite_uri(&mut req, &Scheme::HTTP, &authority).unwrap();
+/// assert_eq!(req.uri(), "http://example.com/users");
+/// ```
+#[derive(Debug, Clone)]
+pub struct Deny<F, Pr>(pub F, pub Pr);
+
+/// Marks a request rejected by [`Deny`], with the path.
+pub(crate) struct Denied(pub(crate) String);
+
+impl<F, Pr> PathRewriter for Deny<F, Pr>
+where
+    F: FnMut(&str) -> bool,
+    Pr: PathRewriter,
+{
+    fn rewrite<'a>(&'a mut self, path: &'a str) -> Cow<'a, str> {
+        self.1.rewrite(path)
+    }
+
+    fn rewrite_uri<B>(
+        &mut self,
+        req: &mut Request<B>,
+        scheme: &Scheme,
+        authority: &Authority,
+    ) -> Result<(), HttpError> {
+        let path = req.uri().path();
+        if (self.0)(path) {
+            let denied = Denied(path.to_owned());
+            req.extensions_mut().insert(denied);
+            return Ok(());
+        }
+        self.1.rewrite_uri(req, scheme, authority)
+    }
+}
+
 /// Trims a suffix if exists.
 ///
 /// ```
@@ -147,6 +428,7 @@ impl PathRewriter for TrimPrefix<'_> {
 /// assert_eq!(TrimSuffix("bar").rewrite("foobarfoo"), "foobarfoo");
 /// ```
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TrimSuffix<'a>(pub &'a str);
 
 impl PathRewriter for TrimSuffix<'_> {
@@ -166,6 +448,7 @@ impl PathRewriter for TrimSuffix<'_> {
 /// assert_eq!(AppendPrefix("foo").rewrite("bar"), "foobar");
 /// ```
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AppendPrefix<'a>(pub &'a str);
 
 impl PathRewriter for AppendPrefix<'_> {
@@ -184,6 +467,7 @@ impl PathRewriter for AppendPrefix<'_> {
 /// assert_eq!(AppendSuffix("foo").rewrite("bar"), "barfoo");
 /// ```
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AppendSuffix<'a>(pub &'a str);
 
 impl PathRewriter for AppendSuffix<'_> {
@@ -265,10 +549,538 @@ where
     }
 }
 
+/// Converts the `path` by a function of the method and the path, *e.g.* to send reads and writes
+/// to different paths.
+///
+/// The type of the function must be `for<'a> FnMut(&Method, &'a str) -> String`.
+///
+/// **The method is known only to [`rewrite_uri()`](PathRewriter::rewrite_uri), which the services
+/// call on the rewriter they are built with. [`rewrite()`](PathRewriter::rewrite) has no request,
+/// and passes `GET` whatever the method is.** So `MethodFunc` must be the rewriter of the service
+/// itself, or be wrapped only in [`Deny`], [`RewriteHost`] or an [`Option`], which forward
+/// `rewrite_uri()`. Inside a tuple or a `dyn PathRewriter`, or in
+/// [`build_async()`](crate::ReusedServiceBuilder::build_async), which all call `rewrite()`,
+/// every request is rewritten as `GET`.
+///
+/// ```
+/// # use reverse_proxy_service::rewrite::{MethodFunc, PathRewriter};
+/// # use http::uri::{Authority, Scheme};
+/// # use http::{Method, Request};
+/// let mut rw = MethodFunc(|method: &Method, path: &str| match *method {
+///     Method::GET | Method::HEAD => format!("/read{path}"),
+///     _ => format!("/write{path}"),
+/// });
+///
+/// let mut req = Request::post("/users").body(()).unwrap();
+/// let authority = Authority::from_static("example.com");
+/// rw.rewrite_uri(&mut req, &Scheme::HTTP, &authority).unwrap();
+/// assert_eq!(req.uri(), "http://example.com/write/users");
+/// ```
+#[derive(Debug, Clone)]
+pub struct MethodFunc<F>(pub F);
+
+impl<F> PathRewriter for MethodFunc<F>
+where
+    for<'a> F: FnMut(&Method, &'a str) -> String,
+{
+    fn rewrite<'a>(&'a mut self, path: &'a str) -> Cow<'a, str> {
+        self.0(&Method::GET, path).into()
+    }
+
+    fn rewrite_uri<B>(
+        &mut self,
+        req: &mut Request<B>,
+        scheme: &Scheme,
+        authority: &Authority,
+    ) -> Result<(), HttpError> {
+        let path = self.0(req.method(), req.uri().path());
+        *req.uri_mut() = replace_path(req.uri(), &path, scheme, authority)?;
+        Ok(())
+    }
+}
+
+/// Adds or removes a trailing slash. The root `/` is never touched.
+///
+/// ```
+/// # use reverse_proxy_service::rewrite::{PathRewriter, TrailingSlash};
+/// assert_eq!(TrailingSlash::Add.rewrite("/foo"), "/foo/");
+/// assert_eq!(TrailingSlash::Add.rewrite("/foo/"), "/foo/");
+/// assert_eq!(TrailingSlash::Add.rewrite("/"), "/");
+///
+/// assert_eq!(TrailingSlash::Remove.rewrite("/foo/"), "/foo");
+/// assert_eq!(TrailingSlash::Remove.rewrite("/foo//"), "/foo");
+/// assert_eq!(TrailingSlash::Remove.rewrite("/foo"), "/foo");
+/// assert_eq!(TrailingSlash::Remove.rewrite("/"), "/");
+///
+/// assert_eq!(TrailingSlash::Leave.rewrite("/foo/"), "/foo/");
+/// assert_eq!(TrailingSlash::Leave.rewrite("/foo"), "/foo");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrailingSlash {
+    Add,
+    Remove,
+    Leave,
+}
+
+impl PathRewriter for TrailingSlash {
+    fn rewrite<'a>(&mut self, path: &'a str) -> Cow<'a, str> {
+        match self {
+            Self::Add if !path.is_empty() && !path.ends_with('/') => {
+                let mut ret = String::with_capacity(path.len() + 1);
+                ret.push_str(path);
+                ret.push('/');
+                ret.into()
+            }
+            Self::Remove => match path.trim_end_matches('/') {
+                "" => path.into(),
+                trimmed => trimmed.into(),
+            },
+            _ => path.into(),
+        }
+    }
+}
+
+/// Routing table of prefixes: replaces the longest registered prefix of a path with its
+/// replacement, *e.g.* an empty one to strip the prefix. A path without a registered prefix is
+/// left as is.
+///
+/// The prefixes are matched as strings, so that `/a` is a prefix of `/ab` as well as `/a/b`. If
+/// the same prefix is registered twice, the last replacement wins.
+///
+/// ```
+/// # use reverse_proxy_service::rewrite::{PathRewriter, PrefixTable};
+/// let mut rw = PrefixTable::new(vec![
+///     ("/api/v1", "/legacy".into()),
+///     ("/api", "".into()),
+/// ]);
+/// assert_eq!(rw.rewrite("/api/v1/users"), "/legacy/users");
+/// assert_eq!(rw.rewrite("/api/v2/users"), "/v2/users");
+/// assert_eq!(rw.rewrite("/static/app.js"), "/static/app.js");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrefixTable<'a>(BTreeMap<&'a str, Cow<'a, str>>);
+
+impl<'a> PrefixTable<'a> {
+    pub fn new(rules: Vec<(&'a str, Cow<'a, str>)>) -> Self {
+        rules.into_iter().collect()
+    }
+
+    /// The length of the longest registered prefix of `path`, and its replacement.
+    fn lookup<'s>(&'s self, path: &'s str) -> Option<(usize, &'s str)> {
+        // The prefixes of `path` are sorted by length, and none of them is greater than `path`.
+        self.0
+            .range::<&str, _>(..=path)
+            .rev()
+            .find(|(prefix, _)| path.starts_with(**prefix))
+            .map(|(prefix, replacement)| (prefix.len(), replacement.as_ref()))
+    }
+}
+
+impl<'a> FromIterator<(&'a str, Cow<'a, str>)> for PrefixTable<'a> {
+    fn from_iter<I: IntoIterator<Item = (&'a str, Cow<'a, str>)>>(iter: I) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
+
+impl PathRewriter for PrefixTable<'_> {
+    fn rewrite<'a>(&'a mut self, path: &'a str) -> Cow<'a, str> {
+        match self.lookup(path) {
+            Some((len, "")) => path[len..].into(),
+            Some((len, replacement)) => format!("{replacement}{}", &path[len..]).into(),
+            None => path.into(),
+        }
+    }
+}
+
+/// A built-in rewriter parsed from a string, *e.g.* of a configuration file.
+///
+/// A string is a directive and its arguments separated by `:`. The last argument takes the rest
+/// of the string, so that it may contain `:`, except for the regex patterns, which are split off
+/// the replacement at the last `:`.
+///
+/// | String                         | Rewriter                            |
+/// |--------------------------------|-------------------------------------|
+/// | `identity`                     | [`Identity`]                        |
+/// | `static:PATH`                  | [`Static(PATH)`](Static)            |
+/// | `replace:OLD:NEW`              | [`ReplaceAll(OLD, NEW)`](ReplaceAll) |
+/// | `replace-n:N:OLD:NEW`          | [`ReplaceN(OLD, NEW, N)`](ReplaceN) |
+/// | `trim-prefix:P` or `trim:P`    | [`TrimPrefix(P)`](TrimPrefix)       |
+/// | `trim-suffix:S`                | [`TrimSuffix(S)`](TrimSuffix)       |
+/// | `append-prefix:P`              | [`AppendPrefix(P)`](AppendPrefix)   |
+/// | `append-suffix:S`              | [`AppendSuffix(S)`](AppendSuffix)   |
+/// | `regex:RE:NEW`                 | [`RegexAll(RE, NEW)`](RegexAll)     |
+/// | `regex-n:N:RE:NEW`             | [`RegexN(RE, NEW, N)`](RegexN)      |
+///
+/// ```
+/// # use reverse_proxy_service::rewrite::{PathRewriter, Rewrite};
+/// let mut rw: Rewrite = "trim:/api".parse().unwrap();
+/// assert_eq!(rw.rewrite("/api/foo"), "/foo");
+///
+/// let mut rw: Rewrite = r"regex:^/(\d+):/items/$1".parse().unwrap();
+/// assert_eq!(rw.rewrite("/42"), "/items/42");
+/// ```
+///
+/// With the `serde` feature, a rule is (de)serialized as the directive tagging its arguments,
+/// *e.g.* `{"trim-prefix": "/api"}` or `{"regex": ["^/(\\d+)", "/items/$1"]}` in JSON, and
+/// `"identity"` for [`Identity`](Self::Identity). A rule read from a config file is a
+/// [`PathRewriter`] itself, to be chained in a tuple or boxed by [`to_boxed()`](Self::to_boxed).
+#[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+#[non_exhaustive]
+pub enum Rewrite {
+    Identity,
+    Static(String),
+    #[cfg_attr(feature = "serde", serde(rename = "replace"))]
+    ReplaceAll(String, String),
+    ReplaceN(String, String, usize),
+    TrimPrefix(String),
+    TrimSuffix(String),
+    AppendPrefix(String),
+    AppendSuffix(String),
+    #[cfg_attr(feature = "serde", serde(rename = "regex"))]
+    RegexAll(
+        #[cfg_attr(feature = "serde", serde(with = "pattern"))] LibRegex,
+        String,
+    ),
+    #[cfg_attr(feature = "serde", serde(rename = "regex-n"))]
+    RegexN(
+        #[cfg_attr(feature = "serde", serde(with = "pattern"))] LibRegex,
+        String,
+        usize,
+    ),
+}
+
+/// (De)serializes a regex as its pattern.
+#[cfg(feature = "serde")]
+mod pattern {
+    use regex::Regex;
+    use serde::{de::Error as _, Deserialize, Deserializer, Serializer};
+
+    pub(super) fn serialize<S: Serializer>(re: &Regex, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(re.as_str())
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Regex, D::Error> {
+        let pattern = String::deserialize(d)?;
+        Regex::new(&pattern).map_err(D::Error::custom)
+    }
+}
+
+/// The error of parsing a [`Rewrite`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseRewriteError(String);
+
+impl fmt::Display for ParseRewriteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for ParseRewriteError {}
+
+impl FromStr for Rewrite {
+    type Err = ParseRewriteError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (directive, args) = s.split_once(':').unwrap_or((s, ""));
+        let err = |msg: &str| ParseRewriteError(format!("`{directive}`: {msg}"));
+        let pair = |args: &'_ str| {
+            args.split_once(':')
+                .map(|(old, new)| (old.to_owned(), new.to_owned()))
+                .ok_or_else(|| err("expected two arguments separated by `:`"))
+        };
+        let regex = |args: &'_ str| {
+            let (re, new) = args
+                .rsplit_once(':')
+                .ok_or_else(|| err("expected a pattern and a replacement separated by `:`"))?;
+            let re = LibRegex::new(re).map_err(|e| err(&e.to_string()))?;
+            Ok((re, new.to_owned()))
+        };
+        let count = |args: &'_ str| {
+            let (n, rest) = args.split_once(':').unwrap_or((args, ""));
+            let n = n
+                .parse()
+                .map_err(|_| err(&format!("invalid count `{n}`")))?;
+            Ok::<_, ParseRewriteError>((n, rest.to_owned()))
+        };
+
+        let rw = match directive {
+            "identity" if args.is_empty() => Self::Identity,
+            "identity" => return Err(err("expected no arguments")),
+            "static" => Self::Static(args.to_owned()),
+            "replace" => {
+                let (old, new) = pair(args)?;
+                Self::ReplaceAll(old, new)
+            }
+            "replace-n" => {
+                let (n, args) = count(args)?;
+                let (old, new) = pair(&args)?;
+                Self::ReplaceN(old, new, n)
+            }
+            "trim-prefix" | "trim" => Self::TrimPrefix(args.to_owned()),
+            "trim-suffix" => Self::TrimSuffix(args.to_owned()),
+            "append-prefix" => Self::AppendPrefix(args.to_owned()),
+            "append-suffix" => Self::AppendSuffix(args.to_owned()),
+            "regex" => {
+                let (re, new) = regex(args)?;
+                Self::RegexAll(re, new)
+            }
+            "regex-n" => {
+                let (n, args) = count(args)?;
+                let (re, new) = regex(&args)?;
+                Self::RegexN(re, new, n)
+            }
+            _ => {
+                return Err(ParseRewriteError(format!(
+                    "unknown directive `{directive}`"
+                )))
+            }
+        };
+        Ok(rw)
+    }
+}
+
+impl TryFrom<&str> for Rewrite {
+    type Error = ParseRewriteError;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl Rewrite {
+    /// A boxed copy of the rule, *e.g.* to keep it with the rewriters of other types in a
+    /// `Vec<Box<dyn PathRewriter>>`.
+    ///
+    /// ```
+    /// # use reverse_proxy_service::rewrite::{AppendPrefix, PathRewriter, Rewrite};
+    /// let rule: Rewrite = "trim:/api".parse().unwrap();
+    /// let mut rules: Vec<Box<dyn PathRewriter>> =
+    ///     vec![rule.to_boxed(), Box::new(AppendPrefix("/v2"))];
+    /// assert_eq!(rules[0].rewrite("/api/foo"), "/foo");
+    /// ```
+    pub fn to_boxed(&self) -> Box<dyn PathRewriter> {
+        Box::new(self.clone())
+    }
+}
+
+impl PathRewriter for Rewrite {
+    fn rewrite<'a>(&'a mut self, path: &'a str) -> Cow<'a, str> {
+        match self {
+            Self::Identity => path.into(),
+            Self::Static(new) => new.as_str().into(),
+            Self::ReplaceAll(old, _) | Self::ReplaceN(old, _, _) if !path.contains(&**old) => {
+                path.into()
+            }
+            Self::ReplaceAll(old, new) => path.replace(&**old, new).into(),
+            Self::ReplaceN(old, new, n) => path.replacen(&**old, new, *n).into(),
+            Self::TrimPrefix(prefix) => path.strip_prefix(&**prefix).unwrap_or(path).into(),
+            Self::TrimSuffix(suffix) => path.strip_suffix(&**suffix).unwrap_or(path).into(),
+            Self::AppendPrefix(prefix) => format!("{prefix}{path}").into(),
+            Self::AppendSuffix(suffix) => format!("{path}{suffix}").into(),
+            Self::RegexAll(re, new) => re.replace_all(path, new.as_str()),
+            Self::RegexN(re, new, n) => re.replacen(path, *n, new.as_str()),
+        }
+    }
+}
+
+macro_rules! impl_tuple {
+    ($($rw:ident),+) => {
+        impl<$($rw: PathRewriter),+> PathRewriter for ($($rw,)+) {
+            #[allow(non_snake_case)]
+            fn rewrite<'a>(&'a mut self, path: &'a str) -> Cow<'a, str> {
+                let ($($rw,)+) = self;
+                let path = Cow::Borrowed(path);
+                $(
+                    let path = match path {
+                        Cow::Borrowed(path) => $rw.rewrite(path),
+                        Cow::Owned(path) => Cow::Owned($rw.rewrite(&path).into_owned()),
+                    };
+                )+
+                path
+            }
+        }
+    };
+}
+
+impl_tuple!(A1, A2);
+impl_tuple!(A1, A2, A3);
+impl_tuple!(A1, A2, A3, A4);
+impl_tuple!(A1, A2, A3, A4, A5);
+impl_tuple!(A1, A2, A3, A4, A5, A6);
+impl_tuple!(A1, A2, A3, A4, A5, A6, A7);
+impl_tuple!(A1, A2, A3, A4, A5, A6, A7, A8);
+
+/// `Some` rewrites by the inner rewriter, and `None` leaves the path as is, like [`Identity`].
+///
+/// This is handy for a rule enabled by configuration.
+///
+/// ```
+/// # use reverse_proxy_service::rewrite::{PathRewriter, TrimPrefix};
+/// # use std::borrow::Cow;
+/// let mut rw = Some(TrimPrefix("/api"));
+/// assert_eq!(rw.rewrite("/api/foo"), "/foo");
+///
+/// let mut rw: Option<TrimPrefix> = None;
+/// let path = rw.rewrite("/api/foo");
+/// assert_eq!(path, "/api/foo");
+/// // Not allocated
+/// assert!(matches!(path, Cow::Borrowed(_)));
+/// ```
+impl<Pr: PathRewriter> PathRewriter for Option<Pr> {
+    fn rewrite<'a>(&'a mut self, path: &'a str) -> Cow<'a, str> {
+        match self {
+            Some(rw) => rw.rewrite(path),
+            None => path.into(),
+        }
+    }
+
+    fn rewrite_uri<B>(
+        &mut self,
+        req: &mut Request<B>,
+        scheme: &Scheme,
+        authority: &Authority,
+    ) -> Result<(), HttpError> {
+        match self {
+            Some(rw) => rw.rewrite_uri(req, scheme, authority),
+            None => Identity.rewrite_uri(req, scheme, authority),
+        }
+    }
+}
+
+/// Forwards to the trait object, *e.g.* to build a service from a rewriter chosen at runtime
+/// without boxing it again.
+///
+/// Only [`rewrite()`](PathRewriter::rewrite) is dispatched, and so the headers set by the
+/// [`rewrite_uri()`](PathRewriter::rewrite_uri) of the inner rewriter, *e.g.*
+/// [`TrimPrefixForwarded`], are not.
+///
+/// ```
+/// # use reverse_proxy_service::rewrite::{AppendPrefix, PathRewriter, TrimPrefix};
+/// fn rewrite<Pr: PathRewriter>(mut rw: Pr, path: &str) -> String {
+///     rw.rewrite(path).into_owned()
+/// }
+///
+/// let mut rules: Vec<Box<dyn PathRewriter>> =
+///     vec![Box::new(TrimPrefix("/api")), Box::new(AppendPrefix("/v2"))];
+/// let rw: &mut dyn PathRewriter = rules[0].as_mut();
+/// assert_eq!(rewrite(rw, "/api/foo"), "/foo");
+/// ```
+impl PathRewriter for &mut (dyn PathRewriter + '_) {
+    fn rewrite<'a>(&'a mut self, path: &'a str) -> Cow<'a, str> {
+        (**self).rewrite(path)
+    }
+}
+
+/// Produces a [`PathRewriter`] per request from a function.
+///
+/// The type of the function must be `FnMut(&Request<B>) -> Pr` where `Pr: PathRewriter`, so the
+/// rule can depend on the method, headers *etc.* of the request. This is not a [`PathRewriter`]
+/// by itself; build a service by
+/// [`Builder::build_with_path_fn()`](crate::ReusedServiceBuilder::build_with_path_fn).
+pub struct PathFn<F>(pub F);
+
+/// Wraps an [`AsyncPathRewriter`], awaited before sending the request.
+///
+/// This is not a [`PathRewriter`] by itself; build a service by
+/// [`Builder::build_async()`](crate::ReusedServiceBuilder::build_async).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AsyncPath<Pr>(pub Pr);
+
+/// The URI of a request as sent by the client, before rewritten.
+///
+/// Inserted into the extensions of the request and the response by
+/// [`Builder::preserve_original_uri_extension()`](crate::ReusedServiceBuilder::preserve_original_uri_extension).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OriginalUri(pub Uri);
+
 #[cfg(test)]
 mod test {
     use super::*;
 
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde() {
+        let rules = r#"[
+            "identity",
+            {"static": "/baz"},
+            {"replace": ["foo", "goo"]},
+            {"replace-n": ["foo", "goo", 1]},
+            {"trim-prefix": "/api"},
+            {"trim-suffix": "/"},
+            {"append-prefix": "/v2"},
+            {"append-suffix": ".json"},
+            {"regex": ["^/(\\d+)", "/items/$1"]},
+            {"regex-n": ["o", "0", 2]}
+        ]"#;
+        let mut rules: Vec<Rewrite> = serde_json::from_str(rules).unwrap();
+        let rewritten = rules
+            .iter_mut()
+            .map(|rw| rw.rewrite("/42/foo/foo").into_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rewritten,
+            [
+                "/42/foo/foo",
+                "/baz",
+                "/42/goo/goo",
+                "/42/goo/foo",
+                "/42/foo/foo",
+                "/42/foo/foo",
+                "/v2/42/foo/foo",
+                "/42/foo/foo.json",
+                "/items/42/foo/foo",
+                "/42/f00/foo",
+            ]
+        );
+
+        let json = serde_json::to_string(&rules).unwrap();
+        let again: Vec<Rewrite> = serde_json::from_str(&json).unwrap();
+        assert_eq!(serde_json::to_string(&again).unwrap(), json);
+        let mut boxed: Vec<Box<dyn PathRewriter>> = again.iter().map(Rewrite::to_boxed).collect();
+        let rewritten_boxed = boxed
+            .iter_mut()
+            .map(|rw| rw.rewrite("/42/foo/foo").into_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(rewritten_boxed, rewritten);
+        assert!(json.contains(r#"{"regex":["^/(\\d+)","/items/$1"]}"#));
+
+        let err = serde_json::from_str::<Rewrite>(r#"{"regex": ["(", ""]}"#);
+        assert!(err.is_err());
+        let err = serde_json::from_str::<Rewrite>(r#"{"trim": "/api"}"#);
+        assert!(err.is_err());
+
+        let json = serde_json::to_string(&(TrimPrefix("/api"), ReplaceN("a", "b", 2))).unwrap();
+        assert_eq!(json, r#"["/api",["a","b",2]]"#);
+        let rw: (TrimPrefix, ReplaceN) = serde_json::from_str(&json).unwrap();
+        assert_eq!(rw, (TrimPrefix("/api"), ReplaceN("a", "b", 2)));
+        let json = r#""/foo""#;
+        assert_eq!(
+            serde_json::from_str::<Static>(json).unwrap(),
+            Static("/foo")
+        );
+        assert_eq!(
+            serde_json::from_str::<TrimSuffix>(json).unwrap(),
+            TrimSuffix("/foo")
+        );
+        assert_eq!(
+            serde_json::from_str::<AppendPrefix>(json).unwrap(),
+            AppendPrefix("/foo")
+        );
+        assert_eq!(
+            serde_json::from_str::<AppendSuffix>(json).unwrap(),
+            AppendSuffix("/foo")
+        );
+        assert_eq!(
+            serde_json::to_string(&ReplaceAll("a", "b")).unwrap(),
+            r#"["a","b"]"#
+        );
+    }
+
     #[test]
     fn rewrite_static() {
         let path = "/foo/bar";
@@ -301,6 +1113,14 @@ mod test {
         let mut rw = TrimPrefix("foo");
         assert_eq!(rw.rewrite(path), "/foo/foo/bar");
 
+        let mut rw = TrimPrefixes(&["/foo", "/foo/foo", "/bar"]);
+        assert!(matches!(rw.rewrite(path), Cow::Borrowed("/bar")));
+        assert_eq!(rw.rewrite("/bar/foo"), "/foo");
+        assert_eq!(rw.rewrite("/foo/bar"), "/bar");
+        assert!(matches!(rw.rewrite("/baz/foo"), Cow::Borrowed("/baz/foo")));
+        let mut rw = TrimPrefixes(&[]);
+        assert_eq!(rw.rewrite(path), path);
+
         let path = "/bar/foo/foo";
         let mut rw = TrimSuffix("foo");
         assert_eq!(rw.rewrite(path), "/bar/foo/");
@@ -310,6 +1130,25 @@ mod test {
         assert_eq!(rw.rewrite(path), "/bar/foo/foo");
     }
 
+    #[test]
+    fn prefix_table() {
+        let mut rw = PrefixTable::new(vec![
+            ("/a", "/x".into()),
+            ("/ab", "/y".into()),
+            ("/abc/", "".into()),
+        ]);
+        assert_eq!(rw.rewrite("/ab/foo"), "/y/foo");
+        assert_eq!(rw.rewrite("/abd"), "/yd");
+        assert_eq!(rw.rewrite("/ac"), "/xc");
+        assert_eq!(rw.rewrite("/abc/foo"), "foo");
+        assert_eq!(rw.rewrite("/abc"), "/yc");
+        assert_eq!(rw.rewrite("/b/foo"), "/b/foo");
+        assert_eq!(rw.rewrite("/"), "/");
+
+        let mut rw = PrefixTable::new(Vec::new());
+        assert_eq!(rw.rewrite("/a"), "/a");
+    }
+
     #[test]
     fn append() {
         let path = "/foo/bar";
@@ -339,10 +1178,126 @@ mod test {
         assert_eq!(rw.rewrite(path), "/10-21-2021/12-02-2021/2022/01/13");
     }
 
+    #[test]
+    fn trailing_slash() {
+        assert!(matches!(
+            TrailingSlash::Add.rewrite("/foo/"),
+            Cow::Borrowed("/foo/")
+        ));
+        assert!(matches!(
+            TrailingSlash::Remove.rewrite("/foo/"),
+            Cow::Borrowed("/foo")
+        ));
+        assert!(matches!(
+            TrailingSlash::Remove.rewrite("//"),
+            Cow::Borrowed("//")
+        ));
+        assert_eq!(TrailingSlash::Add.rewrite("/foo/bar"), "/foo/bar/");
+    }
+
     #[test]
     fn func() {
         let path = "/abcdefg";
         let mut rw = Func(|path: &str| path.len().to_string());
         assert_eq!(rw.rewrite(path), "8");
     }
+
+    #[test]
+    fn tuple() {
+        let mut rw = (TrimPrefix("/api"), AppendPrefix("/v2"));
+        assert_eq!(rw.rewrite("/api/foo"), "/v2/foo");
+
+        let mut rw = (AppendPrefix("/v2"), TrimPrefix("/api"));
+        assert_eq!(rw.rewrite("/api/foo"), "/v2/api/foo");
+
+        let mut rw = (TrimPrefix("/api"), TrimSuffix("/"), AppendSuffix(".json"));
+        assert!(matches!(rw.rewrite("/api/foo/"), Cow::Owned(_)));
+        assert_eq!(rw.rewrite("/api/foo/"), "/foo.json");
+
+        let mut rw = (
+            ReplaceAll("a", "b"),
+            ReplaceAll("b", "c"),
+            ReplaceAll("c", "d"),
+            AppendPrefix("/x"),
+        );
+        assert_eq!(rw.rewrite("/abc"), "/x/ddd");
+
+        let mut rw = (TrimPrefix("/api"), Identity);
+        assert!(matches!(rw.rewrite("/api/foo"), Cow::Borrowed("/foo")));
+    }
+
+    #[test]
+    fn parse_rewrite() {
+        let rewrite = |s: &str, path: &str| {
+            let rw = s.parse::<Rewrite>();
+            assert!(rw.is_ok(), "{s}");
+            rw.unwrap().rewrite(path).into_owned()
+        };
+        assert_eq!(rewrite("identity", "/foo"), "/foo");
+        assert_eq!(rewrite("static:/", "/foo"), "/");
+        assert_eq!(rewrite("replace:foo:bar", "/foo/foo"), "/bar/bar");
+        assert_eq!(rewrite("replace:foo:a:b", "/foo"), "/a:b");
+        assert_eq!(rewrite("replace-n:1:foo:bar", "/foo/foo"), "/bar/foo");
+        assert_eq!(rewrite("trim:/api", "/api/foo"), "/foo");
+        assert_eq!(rewrite("trim-prefix:/api", "/api/foo"), "/foo");
+        assert_eq!(rewrite("trim-suffix:/", "/foo/"), "/foo");
+        assert_eq!(rewrite("append-prefix:/v2", "/foo"), "/v2/foo");
+        assert_eq!(rewrite("append-suffix:/", "/foo"), "/foo/");
+        assert_eq!(rewrite(r"regex:(?:\d+):n", "/1/22"), "/n/n");
+        assert_eq!(rewrite(r"regex-n:1:\d+:n", "/1/22"), "/n/22");
+
+        let err = |s: &str| s.parse::<Rewrite>().unwrap_err().to_string();
+        assert_eq!(err("rot13:/api"), "unknown directive `rot13`");
+        assert!(err("replace:foo").starts_with("`replace`: expected two arguments"));
+        assert_eq!(err("replace-n:x:foo:bar"), "`replace-n`: invalid count `x`");
+        assert!(err("regex:(:foo").starts_with("`regex`: "));
+        assert!(Rewrite::try_from("identity:x").is_err());
+    }
+
+    #[test]
+    fn dyn_rewriter() {
+        fn rewrite_twice<Pr: PathRewriter>(mut rw: Pr, path: &str) -> String {
+            let once = rw.rewrite(path).into_owned();
+            rw.rewrite(&once).into_owned()
+        }
+
+        let mut inner = ReplaceN("foo", "bar", 1);
+        let rw: &mut dyn PathRewriter = &mut inner;
+        assert_eq!(rewrite_twice(rw, "/foo/foo/foo"), "/bar/bar/foo");
+
+        let mut inner = (TrimPrefix("/api"), AppendSuffix("/"));
+        let mut rw: &mut dyn PathRewriter = &mut inner;
+        let mut req = Request::builder().uri("/api/foo?q=1").body(()).unwrap();
+        let authority = Authority::from_static("example.com");
+        let res = PathRewriter::rewrite_uri(&mut rw, &mut req, &Scheme::HTTP, &authority);
+        assert!(res.is_ok());
+        assert_eq!(req.uri(), "http://example.com/foo/?q=1");
+    }
+
+    #[test]
+    fn trim_prefix_forwarded() {
+        let authority = Authority::from_static("example.com");
+
+        let mut req = Request::builder()
+            .uri("/service-a/foo?bar=baz")
+            .header("x-forwarded-prefix", "/evil")
+            .body(())
+            .unwrap();
+        let res =
+            TrimPrefixForwarded("/service-a").rewrite_uri(&mut req, &Scheme::HTTP, &authority);
+        assert!(res.is_ok());
+        assert_eq!(req.uri(), "http://example.com/foo?bar=baz");
+        assert_eq!(req.headers()["x-forwarded-prefix"], "/service-a");
+
+        let mut req = Request::builder()
+            .uri("/service-b/foo")
+            .header("x-forwarded-prefix", "/evil")
+            .body(())
+            .unwrap();
+        let res =
+            TrimPrefixForwarded("/service-a").rewrite_uri(&mut req, &Scheme::HTTP, &authority);
+        assert!(res.is_ok());
+        assert_eq!(req.uri(), "http://example.com/service-b/foo");
+        assert!(!req.headers().contains_key("x-forwarded-prefix"));
+    }
 }
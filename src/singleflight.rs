@@ -0,0 +1,161 @@
+use crate::cache::{self, Key, MAX_BODY};
+use crate::config::Config;
+use crate::future::{collect, is_event_stream, set_content_length};
+use crate::Error;
+
+use http::header::{self, HeaderMap, HeaderName};
+use http::{Request, Response, StatusCode, Version};
+
+use hyper::body::{Body, Bytes, HttpBody};
+
+use tokio::sync::oneshot;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// A response fanned out to the requests waiting for it.
+pub(crate) struct Shared {
+    status: StatusCode,
+    version: Version,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+impl Shared {
+    fn response(&self) -> Response<Body> {
+        let mut res = Response::new(Body::from(self.body.clone()));
+        *res.status_mut() = self.status;
+        *res.version_mut() = self.version;
+        *res.headers_mut() = self.headers.clone();
+        res
+    }
+}
+
+type Waiters = Vec<oneshot::Sender<Arc<Shared>>>;
+
+/// The requests in flight, keyed like the cache, shared by the services built from the same
+/// builder.
+#[derive(Default)]
+pub(crate) struct Singleflight {
+    flights: Mutex<HashMap<Key, Waiters>>,
+}
+
+impl fmt::Debug for Singleflight {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Singleflight").finish_non_exhaustive()
+    }
+}
+
+pub(crate) enum Flight {
+    /// The first of the identical requests, which is sent to the upstream.
+    Leader(Leader),
+    /// Waits for the response of the leader, which is dropped if it cannot be shared.
+    Follower(oneshot::Receiver<Arc<Shared>>),
+}
+
+impl Flight {
+    /// Waits for the shared response if a follower, or returns the leader.
+    pub(crate) async fn wait(self) -> (Option<Leader>, Option<Response<Body>>) {
+        match self {
+            Self::Leader(leader) => (Some(leader), None),
+            Self::Follower(rx) => (None, rx.await.ok().map(|shared| shared.response())),
+        }
+    }
+}
+
+impl Singleflight {
+    /// Joins the flight of `req`, or returns `None` if it must be sent on its own.
+    ///
+    /// The credentials of the client are not shared with the others, unless they are in `vary`.
+    pub(crate) fn join<B: HttpBody>(
+        self: &Arc<Self>,
+        req: &Request<B>,
+        vary: &[HeaderName],
+    ) -> Option<Flight> {
+        let personal = [header::AUTHORIZATION, header::COOKIE]
+            .iter()
+            .any(|name| req.headers().contains_key(name) && !vary.contains(name));
+        if personal || req.headers().contains_key(header::UPGRADE) || !req.body().is_end_stream() {
+            return None;
+        }
+        let key = cache::key(req, vary)?;
+
+        let mut flights = self.flights.lock().unwrap_or_else(PoisonError::into_inner);
+        match flights.get_mut(&key) {
+            Some(waiters) => {
+                let (tx, rx) = oneshot::channel();
+                waiters.push(tx);
+                Some(Flight::Follower(rx))
+            }
+            None => {
+                flights.insert(key.clone(), Vec::new());
+                Some(Flight::Leader(Leader {
+                    flights: self.clone(),
+                    key: Some(key),
+                }))
+            }
+        }
+    }
+}
+
+/// Ends the flight when dropped, so that the followers send their own requests unless the
+/// response is [shared](Self::share).
+pub(crate) struct Leader {
+    flights: Arc<Singleflight>,
+    key: Option<Key>,
+}
+
+impl Leader {
+    /// Buffers `res` and passes a copy to each follower, if it can be shared, *i.e.* the body size
+    /// is known to be at most [`MAX_BODY`], and it sets no cookie.
+    pub(crate) async fn share(
+        mut self,
+        res: Result<Response<Body>, Error>,
+        config: &Config,
+    ) -> Result<Response<Body>, Error> {
+        let res = res?;
+        if res.status() == StatusCode::SWITCHING_PROTOCOLS
+            || res.headers().contains_key(header::SET_COOKIE)
+            || is_event_stream(&res)
+            || res
+                .body()
+                .size_hint()
+                .upper()
+                .is_none_or(|len| len > MAX_BODY as u64)
+        {
+            return Ok(res);
+        }
+        let (mut parts, body) = res.into_parts();
+        let body = config
+            .within_body_timeout(collect(body, MAX_BODY, config.in_flight_bytes.as_ref()))
+            .await?;
+        set_content_length(&mut parts.headers, body.len());
+        let shared = Arc::new(Shared {
+            status: parts.status,
+            version: parts.version,
+            headers: parts.headers.clone(),
+            body: body.clone(),
+        });
+
+        let waiters = self.key.take().and_then(|key| {
+            let flights = &self.flights.flights;
+            let mut flights = flights.lock().unwrap_or_else(PoisonError::into_inner);
+            flights.remove(&key)
+        });
+        for tx in waiters.into_iter().flatten() {
+            let _ = tx.send(shared.clone());
+        }
+        Ok(Response::from_parts(parts, body.into()))
+    }
+}
+
+impl Drop for Leader {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            let flights = &self.flights.flights;
+            let mut flights = flights.lock().unwrap_or_else(PoisonError::into_inner);
+            flights.remove(&key);
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use http::StatusCode;
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The upper bounds of the buckets of [`Stats::latency_histogram()`], except the last one which
+/// has no bound.
+const BUCKETS: [Duration; 8] = [
+    Duration::from_millis(1),
+    Duration::from_millis(5),
+    Duration::from_millis(10),
+    Duration::from_millis(50),
+    Duration::from_millis(100),
+    Duration::from_millis(500),
+    Duration::from_secs(1),
+    Duration::from_secs(5),
+];
+
+/// Counts of the requests proxied by a [`ReusedService`](crate::ReusedService), read by
+/// [`ReusedService::stats()`](crate::ReusedService::stats).
+///
+/// The counts are shared by the services built by the same builder, and their clones.
+///
+/// The latency is the time until the response head is returned, so it does not include the
+/// streaming of the response body. A request rejected before it is sent, *e.g.* by
+/// [`circuit_breaker()`](crate::ReusedServiceBuilder::circuit_breaker), is counted in
+/// [`requests()`](Self::requests) and [`errors()`](Self::errors), but not in the latency.
+#[derive(Debug, Clone, Default)]
+pub struct Stats(Arc<Counts>);
+
+#[derive(Debug, Default)]
+struct Counts {
+    requests: AtomicU64,
+    /// 1xx to 5xx.
+    classes: [AtomicU64; 5],
+    errors: AtomicU64,
+    /// The requests whose latency is recorded.
+    timed: AtomicU64,
+    latency_micros: AtomicU64,
+    max_latency_micros: AtomicU64,
+    buckets: [AtomicU64; BUCKETS.len() + 1],
+}
+
+impl Stats {
+    pub(crate) fn record(&self, status: Option<StatusCode>, latency: Duration) {
+        let counts = &self.0;
+        counts.requests.fetch_add(1, Ordering::Relaxed);
+        match status {
+            Some(status) => {
+                // `StatusCode` allows up to 999, which is in no class.
+                let class = usize::from(status.as_u16() / 100 - 1);
+                if let Some(count) = counts.classes.get(class) {
+                    count.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+            None => {
+                counts.errors.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        counts.timed.fetch_add(1, Ordering::Relaxed);
+        let micros = u64::try_from(latency.as_micros()).unwrap_or(u64::MAX);
+        counts.latency_micros.fetch_add(micros, Ordering::Relaxed);
+        counts
+            .max_latency_micros
+            .fetch_max(micros, Ordering::Relaxed);
+        let bucket = BUCKETS
+            .iter()
+            .position(|&bound| latency <= bound)
+            .unwrap_or(BUCKETS.len());
+        counts.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records a request rejected before it is sent, without its latency.
+    pub(crate) fn record_rejected(&self) {
+        self.0.requests.fetch_add(1, Ordering::Relaxed);
+        self.0.errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// The number of the requests so far, including the ones failed with an
+    /// [`Error`](crate::Error).
+    pub fn requests(&self) -> u64 {
+        self.0.requests.load(Ordering::Relaxed)
+    }
+
+    /// The number of the responses with a status `class`, *i.e.* `2` counts 2xx responses.
+    ///
+    /// Returns 0 unless `class` is in `1..=5`.
+    pub fn status_class(&self, class: u16) -> u64 {
+        match class {
+            1..=5 => self.0.classes[usize::from(class - 1)].load(Ordering::Relaxed),
+            _ => 0,
+        }
+    }
+
+    /// The number of the requests failed with an [`Error`](crate::Error) instead of a response.
+    pub fn errors(&self) -> u64 {
+        self.0.errors.load(Ordering::Relaxed)
+    }
+
+    /// The mean latency, or `None` if no request is sent yet.
+    pub fn mean_latency(&self) -> Option<Duration> {
+        let total = self.0.latency_micros.load(Ordering::Relaxed);
+        let timed = self.0.timed.load(Ordering::Relaxed);
+        (timed > 0).then(|| Duration::from_micros(total / timed))
+    }
+
+    /// The maximum latency so far.
+    pub fn max_latency(&self) -> Duration {
+        Duration::from_micros(self.0.max_latency_micros.load(Ordering::Relaxed))
+    }
+
+    /// The numbers of the requests by latency, each paired with the upper bound of the bucket:
+    /// 1ms, 5ms, 10ms, 50ms, 100ms, 500ms, 1s, 5s and `None` for the rest.
+    pub fn latency_histogram(&self) -> Vec<(Option<Duration>, u64)> {
+        let bounds = BUCKETS.iter().copied().map(Some).chain([None]);
+        bounds
+            .zip(&self.0.buckets)
+            .map(|(bound, count)| (bound, count.load(Ordering::Relaxed)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn record() {
+        let stats = Stats::default();
+        assert_eq!(stats.mean_latency(), None);
+
+        stats.record(Some(StatusCode::OK), Duration::from_millis(2));
+        stats.record(Some(StatusCode::NOT_FOUND), Duration::from_millis(4));
+        stats.record(None, Duration::from_secs(10));
+        assert_eq!(stats.requests(), 3);
+        assert_eq!(stats.status_class(2), 1);
+        assert_eq!(stats.status_class(4), 1);
+        assert_eq!(stats.status_class(5), 0);
+        assert_eq!(stats.status_class(6), 0);
+        assert_eq!(stats.errors(), 1);
+        assert_eq!(stats.max_latency(), Duration::from_secs(10));
+        assert_eq!(stats.mean_latency(), Some(Duration::from_micros(3_335_333)));
+
+        let histogram = stats.latency_histogram();
+        assert_eq!(histogram.len(), 9);
+        assert_eq!(histogram[1], (Some(Duration::from_millis(5)), 2));
+        assert_eq!(histogram[8], (None, 1));
+        assert_eq!(histogram.iter().map(|(_, count)| count).sum::<u64>(), 3);
+
+        // A rejected request has no latency.
+        stats.record_rejected();
+        assert_eq!(stats.requests(), 4);
+        assert_eq!(stats.errors(), 2);
+        assert_eq!(stats.mean_latency(), Some(Duration::from_micros(3_335_333)));
+        let histogram = stats.latency_histogram();
+        assert_eq!(histogram.iter().map(|(_, count)| count).sum::<u64>(), 3);
+    }
+}
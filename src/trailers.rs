@@ -0,0 +1,42 @@
+use crate::exec::Exec;
+use crate::transform::map_body_request;
+
+use http::header::{self, HeaderMap, HeaderValue};
+use http::Request;
+
+use hyper::body::{Body, HttpBody};
+
+/// Appends `trailers` to the body of `req`, and sets `TE: trailers`.
+pub(crate) fn append<B: 'static>(req: Request<B>, trailers: &HeaderMap, exec: &Exec) -> Request<B> {
+    map_body_request(req, |req| append_body(req, trailers.clone(), exec))
+}
+
+fn append_body(req: Request<Body>, trailers: HeaderMap, exec: &Exec) -> Request<Body> {
+    let (mut parts, mut body) = req.into_parts();
+    parts
+        .headers
+        .insert(header::TE, HeaderValue::from_static("trailers"));
+
+    let (mut tx, channel) = Body::channel();
+    exec.spawn(async move {
+        while let Some(chunk) = body.data().await {
+            match chunk {
+                Ok(chunk) => {
+                    if tx.send_data(chunk).await.is_err() {
+                        return;
+                    }
+                }
+                Err(_) => return tx.abort(),
+            }
+        }
+        match body.trailers().await {
+            Ok(sent) => {
+                let mut sent = sent.unwrap_or_default();
+                sent.extend(trailers);
+                let _ = tx.send_trailers(sent).await;
+            }
+            Err(_) => tx.abort(),
+        }
+    });
+    Request::from_parts(parts, channel)
+}
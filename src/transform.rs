@@ -0,0 +1,72 @@
+use http::{Request, Response};
+
+use hyper::body::Body;
+
+use std::any::Any;
+
+/// A transformation of the request and response bodies, *e.g.* compression or filtering, set by
+/// [`Builder::body_transform()`](crate::ReusedServiceBuilder::body_transform).
+///
+/// Both methods receive the whole message, so that a transform can fix the headers describing
+/// the body, *e.g.* remove `Content-Length` of a body changing its length. By default a message
+/// is returned as is.
+///
+/// A body is transformed chunk by chunk, rather than buffered, by forwarding it through
+/// [`Body::channel()`] in a task:
+///
+/// ```
+/// # use reverse_proxy_service::BodyTransform;
+/// use http::{header, Response};
+/// use hyper::body::{Body, HttpBody};
+///
+/// /// Drops the lines of `DEBUG`. The lines must not span the chunks.
+/// struct NoDebug;
+///
+/// impl BodyTransform for NoDebug {
+///     fn transform_response(&self, res: Response<Body>) -> Response<Body> {
+///         let (mut parts, mut body) = res.into_parts();
+///         parts.headers.remove(header::CONTENT_LENGTH);
+///         let (mut tx, filtered) = Body::channel();
+///         tokio::spawn(async move {
+///             while let Some(Ok(chunk)) = body.data().await {
+///                 let lines = String::from_utf8_lossy(&chunk)
+///                     .split_inclusive('\n')
+///                     .filter(|line| !line.starts_with("DEBUG"))
+///                     .collect::<String>();
+///                 if tx.send_data(lines.into()).await.is_err() {
+///                     return;
+///                 }
+///             }
+///         });
+///         Response::from_parts(parts, filtered)
+///     }
+/// }
+/// ```
+pub trait BodyTransform: Send + Sync {
+    /// Transforms a request before it is sent to the upstream.
+    fn transform_request(&self, req: Request<Body>) -> Request<Body> {
+        req
+    }
+
+    /// Transforms a response received from the upstream.
+    fn transform_response(&self, res: Response<Body>) -> Response<Body> {
+        res
+    }
+}
+
+/// Transforms nothing.
+impl BodyTransform for crate::Identity {}
+
+/// Applies `f` to `req` if its body is [`Body`], and returns any other request as is; the
+/// builder offers the options calling this for [`Body`] only.
+pub(crate) fn map_body_request<B, F>(req: Request<B>, f: F) -> Request<B>
+where
+    B: 'static,
+    F: FnOnce(Request<Body>) -> Request<Body>,
+{
+    let mut req = Some(req);
+    if let Some(slot) = <dyn Any>::downcast_mut::<Option<Request<Body>>>(&mut req) {
+        *slot = slot.take().map(f);
+    }
+    req.expect("the request is put back")
+}
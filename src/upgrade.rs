@@ -0,0 +1,173 @@
+use crate::exec::Exec;
+
+use hyper::upgrade::{OnUpgrade, Upgraded};
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::time::Instant;
+
+use std::future::{self, Future};
+use std::io;
+use std::pin::{pin, Pin};
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+
+pub(crate) type UpgradeFn =
+    Arc<dyn Fn(Upgraded, Upgraded) -> Pin<Box<dyn Future<Output = ()> + Send>> + Send + Sync>;
+
+/// Waits for both the connections to be upgraded, and hands them to `f` in a new task.
+pub(crate) fn spawn(exec: &Exec, f: UpgradeFn, client: OnUpgrade, upstream: OnUpgrade) {
+    exec.spawn(async move {
+        match (client.await, upstream.await) {
+            (Ok(client), Ok(upstream)) => f(client, upstream).await,
+            (Err(e), _) | (_, Err(e)) => log::error!("Upgrade failed: {e}"),
+        }
+    });
+}
+
+/// Relays the bytes between `a` and `b` both ways, until both reach EOF, either fails, or no byte
+/// flows for `idle`. Dropping them then closes both connections.
+pub(crate) async fn relay<A, B>(mut a: A, mut b: B, idle: Duration)
+where
+    A: AsyncRead + AsyncWrite + Unpin,
+    B: AsyncRead + AsyncWrite + Unpin,
+{
+    let (mut a_to_b, mut b_to_a) = (Direction::new(), Direction::new());
+    let mut timer = pin!(tokio::time::sleep(idle));
+    future::poll_fn(|cx| {
+        let mut active = false;
+        let results = [
+            a_to_b.poll_relay(cx, &mut a, &mut b, &mut active),
+            b_to_a.poll_relay(cx, &mut b, &mut a, &mut active),
+        ];
+        for res in &results {
+            if let Poll::Ready(Err(e)) = res {
+                log::debug!("Upgraded connection failed: {e}");
+                return Poll::Ready(());
+            }
+        }
+        if results.iter().all(Poll::is_ready) {
+            return Poll::Ready(());
+        }
+        if active {
+            timer.as_mut().reset(Instant::now() + idle);
+        }
+        if timer.as_mut().poll(cx).is_ready() {
+            log::debug!("Upgraded connection idle for {idle:?}");
+            return Poll::Ready(());
+        }
+        Poll::Pending
+    })
+    .await
+}
+
+/// The state of copying from one connection to the other.
+struct Direction {
+    buf: Box<[u8]>,
+    pos: usize,
+    len: usize,
+    eof: bool,
+    unflushed: bool,
+    done: bool,
+}
+
+impl Direction {
+    fn new() -> Self {
+        Self {
+            buf: vec![0; 8 * 1024].into(),
+            pos: 0,
+            len: 0,
+            eof: false,
+            unflushed: false,
+            done: false,
+        }
+    }
+
+    /// Copies from `reader` to `writer` until either would block, setting `active` if any byte
+    /// moves. Shuts down `writer` after EOF of `reader`.
+    fn poll_relay<R, W>(
+        &mut self,
+        cx: &mut Context<'_>,
+        reader: &mut R,
+        writer: &mut W,
+        active: &mut bool,
+    ) -> Poll<io::Result<()>>
+    where
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        if self.done {
+            return Poll::Ready(Ok(()));
+        }
+        loop {
+            if self.pos == self.len && !self.eof {
+                let mut buf = ReadBuf::new(&mut self.buf);
+                match Pin::new(&mut *reader).poll_read(cx, &mut buf) {
+                    Poll::Ready(Ok(())) if buf.filled().is_empty() => self.eof = true,
+                    Poll::Ready(Ok(())) => {
+                        (self.pos, self.len) = (0, buf.filled().len());
+                        *active = true;
+                    }
+                    Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                    Poll::Pending => {
+                        // Nothing more to write for now, so that the written bytes are sent.
+                        if self.unflushed {
+                            ready!(Pin::new(&mut *writer).poll_flush(cx))?;
+                            self.unflushed = false;
+                        }
+                        return Poll::Pending;
+                    }
+                }
+            }
+            while self.pos < self.len {
+                let buf = &self.buf[self.pos..self.len];
+                let n = ready!(Pin::new(&mut *writer).poll_write(cx, buf))?;
+                if n == 0 {
+                    return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+                }
+                self.pos += n;
+                self.unflushed = true;
+                *active = true;
+            }
+            if self.eof {
+                ready!(Pin::new(&mut *writer).poll_shutdown(cx))?;
+                self.done = true;
+                return Poll::Ready(Ok(()));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn relay() {
+        let (mut client, a) = tokio::io::duplex(16);
+        let (b, mut upstream) = tokio::io::duplex(16);
+        let relayed = tokio::spawn(super::relay(a, b, Duration::from_millis(100)));
+
+        // More than the buffers of the pipes, both ways.
+        let data = "relayed ".repeat(100);
+        let (sent, received) = tokio::join!(client.write_all(data.as_bytes()), async {
+            let mut buf = vec![0; data.len()];
+            upstream.read_exact(&mut buf).await.map(|_| buf)
+        });
+        assert!(sent.is_ok());
+        assert_eq!(received.unwrap(), data.as_bytes());
+        upstream.write_all(b"pong").await.unwrap();
+        let mut buf = [0; 4];
+        client.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"pong");
+
+        let started = Instant::now();
+        assert!(relayed.await.is_ok());
+        assert!(started.elapsed() >= Duration::from_millis(90));
+        // Both are closed.
+        assert_eq!(client.read(&mut buf).await.unwrap(), 0);
+        assert_eq!(upstream.read(&mut buf).await.unwrap(), 0);
+    }
+}